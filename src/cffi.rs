@@ -0,0 +1,210 @@
+//! C ABI bindings over `MsgQueue<u8>`/`Control<u8>`, gated behind the `cffi` feature.
+//!
+//! Exposes exactly the functionality a downstream consumer needs via FFI: create a
+//! queue, register/fetch a named subscription, and push/read raw bytes through it.
+//! Handles are opaque pointers; errors are an integer status code plus
+//! `ringbuf_last_error()` for the human-readable reason on the calling thread.
+use crate::core::{Control, MsgQueue};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RingbufStatus {
+    Ok = 0,
+    InvalidArgument = 1,
+    SubscriptionNotFound = 2,
+    Overflow = 3,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(message: String) {
+    LAST_ERROR.with(|slot| {
+        *slot.borrow_mut() = CString::new(message).ok();
+    });
+}
+
+/// returns the last error set on the calling thread, or null if there wasn't one.
+/// The pointer is valid until the next `cffi` call on this thread.
+///
+/// # Safety
+/// Takes no pointer arguments; marked `unsafe` only for consistency with the rest of
+/// this module's FFI surface. The returned pointer must not be freed by the caller and
+/// must not be read past the next `cffi` call on this thread.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_last_error() -> *const c_char {
+    LAST_ERROR.with(|slot| match &*slot.borrow() {
+        Some(cstr) => cstr.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// creates a new queue. Free it with `ringbuf_queue_free`.
+///
+/// # Safety
+/// Allocates and leaks a `Box`; marked `unsafe` only for consistency with the rest of
+/// this module's FFI surface. The returned pointer must eventually be passed to
+/// `ringbuf_queue_free` exactly once, and to no other `cffi` function afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_queue_new() -> *mut MsgQueue<u8> {
+    Box::into_raw(Box::new(MsgQueue::new()))
+}
+
+/// frees a queue created by `ringbuf_queue_new`. A null `queue` is a no-op.
+///
+/// # Safety
+/// `queue` must be either null or a pointer previously returned by
+/// `ringbuf_queue_new` that hasn't already been freed. After this call, `queue` (and
+/// every `Control` obtained from it via `ringbuf_get_subscription`) must not be used
+/// again.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_queue_free(queue: *mut MsgQueue<u8>) {
+    if !queue.is_null() {
+        unsafe {
+            drop(Box::from_raw(queue));
+        }
+    }
+}
+
+unsafe fn name_from_raw(name: *const c_char) -> Result<String, RingbufStatus> {
+    if name.is_null() {
+        set_last_error("name must not be null".to_string());
+        return Err(RingbufStatus::InvalidArgument);
+    }
+    match CStr::from_ptr(name).to_str() {
+        Ok(s) => Ok(s.to_string()),
+        Err(_) => {
+            set_last_error("name is not valid UTF-8".to_string());
+            Err(RingbufStatus::InvalidArgument)
+        }
+    }
+}
+
+/// registers a named subscription on `queue`. Idempotent for an existing name.
+///
+/// # Safety
+/// `queue` must be null or a live pointer from `ringbuf_queue_new` not yet freed.
+/// `name` must be null or a valid, nul-terminated, UTF-8 C string for the duration of
+/// this call.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_set_subscription(
+    queue: *mut MsgQueue<u8>,
+    name: *const c_char,
+) -> RingbufStatus {
+    if queue.is_null() {
+        set_last_error("queue must not be null".to_string());
+        return RingbufStatus::InvalidArgument;
+    }
+    let name = match unsafe { name_from_raw(name) } {
+        Ok(name) => name,
+        Err(status) => return status,
+    };
+    unsafe { (*queue).set_subscription(name) };
+    RingbufStatus::Ok
+}
+
+/// fetches the `Control` handle for `name` and writes it to `*out_control`.
+/// `*out_control` must be freed with `ringbuf_control_free`.
+///
+/// # Safety
+/// `queue` must be null or a live pointer from `ringbuf_queue_new` not yet freed.
+/// `name` must be null or a valid, nul-terminated, UTF-8 C string for the duration of
+/// this call. `out_control` must be null or a valid, writable pointer to a
+/// `*mut Control<u8>`.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_get_subscription(
+    queue: *mut MsgQueue<u8>,
+    name: *const c_char,
+    out_control: *mut *mut Control<u8>,
+) -> RingbufStatus {
+    if queue.is_null() || out_control.is_null() {
+        set_last_error("queue/out_control must not be null".to_string());
+        return RingbufStatus::InvalidArgument;
+    }
+    let name = match unsafe { name_from_raw(name) } {
+        Ok(name) => name,
+        Err(status) => return status,
+    };
+    match unsafe { (*queue).get_subscription(name) } {
+        Ok(control) => {
+            unsafe { *out_control = Box::into_raw(Box::new(control)) };
+            RingbufStatus::Ok
+        }
+        Err(message) => {
+            set_last_error(message);
+            RingbufStatus::SubscriptionNotFound
+        }
+    }
+}
+
+/// frees a `Control` obtained from `ringbuf_get_subscription`. A null `control` is a
+/// no-op.
+///
+/// # Safety
+/// `control` must be either null or a pointer previously returned via
+/// `ringbuf_get_subscription`'s `out_control` that hasn't already been freed. After
+/// this call, `control` must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_control_free(control: *mut Control<u8>) {
+    if !control.is_null() {
+        unsafe {
+            drop(Box::from_raw(control));
+        }
+    }
+}
+
+/// copies `len` bytes from `ptr` into the subscription behind `control`.
+///
+/// # Safety
+/// `control` must be a live pointer from `ringbuf_get_subscription` not yet freed.
+/// `ptr` must be null only if `len` is 0; otherwise it must be valid for reads of
+/// `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_push(
+    control: *mut Control<u8>,
+    ptr: *const u8,
+    len: usize,
+) -> RingbufStatus {
+    if control.is_null() || (ptr.is_null() && len != 0) {
+        set_last_error("control/ptr must not be null".to_string());
+        return RingbufStatus::InvalidArgument;
+    }
+    let data = unsafe { std::slice::from_raw_parts(ptr, len) }.to_vec();
+    match unsafe { (*control).push_data(data) } {
+        Ok(()) => RingbufStatus::Ok,
+        Err(rejected) => {
+            set_last_error(format!("{} bytes rejected by overflow policy", rejected.rejected_len));
+            RingbufStatus::Overflow
+        }
+    }
+}
+
+/// reads at most `cap` bytes into `out_ptr`, writing the actual count to `*out_len`.
+///
+/// # Safety
+/// `control` must be a live pointer from `ringbuf_get_subscription` not yet freed.
+/// `out_ptr` must be valid for writes of `cap` bytes. `out_len` must be a valid,
+/// writable pointer to a `usize`.
+#[no_mangle]
+pub unsafe extern "C" fn ringbuf_read(
+    control: *mut Control<u8>,
+    out_ptr: *mut u8,
+    cap: usize,
+    out_len: *mut usize,
+) -> RingbufStatus {
+    if control.is_null() || out_ptr.is_null() || out_len.is_null() {
+        set_last_error("control/out_ptr/out_len must not be null".to_string());
+        return RingbufStatus::InvalidArgument;
+    }
+    let data = unsafe { (*control).read(cap as u64) };
+    unsafe {
+        ptr::copy_nonoverlapping(data.as_ptr(), out_ptr, data.len());
+        *out_len = data.len();
+    }
+    RingbufStatus::Ok
+}
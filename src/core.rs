@@ -4,27 +4,51 @@
 //!
 //! dynamic mode: buffer block is fixed, can set it with fn `set_dynamic()`
 //!
-use crate::core::BufferCacheMode::{Dynamic, Fixed};
-use core::panicking::panic;
+//! `no_std` is not supported and isn't currently planned: `MsgQueue`,
+//! `MsgQueueInner`, `Subscription` and `BufferCache` reach for `Mutex`, `RwLock`,
+//! `Arc`, `Condvar`, `HashMap` and `std::fs` unconditionally throughout this file.
+//! Getting there needs all of that gated behind a `std` feature with `core`/`alloc`-only
+//! stand-ins picked for each, plus a `Cargo.toml` to declare the feature — this source
+//! tree doesn't have one — which is a rewrite of most of this module, not an addition
+//! to it. `MsgQueue::with_buffer` lets a caller supply a queue's backing storage, which
+//! is useful on its own (e.g. a pre-allocated arena reused across queues), but it is
+//! not `no_std` support and shouldn't be read as partial progress toward it.
+//!
+use crate::core::BufferCacheMode::{Dynamic, Fixed, Priority};
+use futures_core::Stream;
 use std::borrow::BorrowMut;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::UnsafeCell;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
 use std::os::unix::raw::mode_t;
-use std::rc::Rc;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::task::{Context, Poll, Waker};
 
 /// main struct for controlling buffer blocks
 /// Users should crate a new buffer block with fn `add_producer`, and explicitly delete the buffer
 /// blocks with fn `delete_consumer`
 /// If users want to add a producer and produce some data, make sure you call fn `add_producer` ahead,
 /// otherwise there are none data block for storing the data.
+///
+/// `inner` is an `Arc<RwLock<..>>` (not `Rc<RefCell<..>>`) precisely so `Sync`/`Send`
+/// below are true rather than asserted: the map is only write-locked for registration
+/// and configuration (`add_buffer_cache`/`delete_buffer_cache`, the subscription
+/// setters, `set_fixed`/`set_dynamic`/`set_priority_mode`), while the hot
+/// `write`/`read` paths only need a read lock because `BufferCache` synchronizes the
+/// ring itself (one writer lock serializing `write_fixed`, not this outer one).
 pub struct MsgQueue<T> {
-    inner: Rc<RefCell<MsgQueueInner<T>>>,
+    inner: Arc<RwLock<MsgQueueInner<T>>>,
     serial_no: u64,
     running: bool,
 }
 
-unsafe impl<T> Sync for MsgQueue<T> {}
-unsafe impl<T> Send for MsgQueue<T> {}
+unsafe impl<T: Send> Sync for MsgQueue<T> {}
+unsafe impl<T: Send> Send for MsgQueue<T> {}
 
 /// @TODO MsgQueue should manage the exist blocks for querying
 impl<T> MsgQueue<T>
@@ -32,11 +56,18 @@ where
     T: Default + Clone,
 {
     pub fn new() -> MsgQueue<T> {
-        let inner = Rc::new(RefCell::new(MsgQueueInner {
-            buf: HashMap::new(),
+        let inner = Arc::new(RwLock::new(MsgQueueInner {
+            ring: BufferCache::new(),
+            subscriptions: HashMap::new(),
             mode: None,
             buf_size: 0,
             block_length: 0,
+            requeued: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            consumer_limits: Mutex::new(HashMap::new()),
+            dropped_counts: Mutex::new(HashMap::new()),
+            backlog_signal: Mutex::new(()),
+            backlog_changed: Condvar::new(),
         }));
 
         MsgQueue {
@@ -46,21 +77,75 @@ where
         }
     }
 
-    /// only can call before using `get_consumer` and `add_consumer`
-    pub fn set_dynamic(&mut self, block_length: u64) {
-        if self.running == true {
+    /// builds a queue whose Fixed-mode ring is backed by storage the caller already
+    /// allocated (see `BufferCache::with_buffer`), instead of `new`'s internal `Vec`.
+    /// Already in Fixed mode, so `set_fixed`/`set_dynamic`/`set_priority_mode` don't
+    /// need to be called afterwards. The queue isn't marked running until the first
+    /// `get_consumer`/`add_consumer`, so one of them called before that still works,
+    /// and replaces the caller's storage with a freshly allocated ring.
+    ///
+    /// intended for callers that want to control where a queue's memory comes from —
+    /// e.g. a pre-allocated arena reused across queues. Not `no_std` support — see the
+    /// module-level note at the top of this file.
+    pub fn with_buffer(storage: Vec<T>) -> MsgQueue<T> {
+        let inner = Arc::new(RwLock::new(MsgQueueInner {
+            ring: BufferCache::with_buffer(storage),
+            subscriptions: HashMap::new(),
+            mode: Some(Fixed),
+            buf_size: 0,
+            block_length: 0,
+            requeued: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            consumer_limits: Mutex::new(HashMap::new()),
+            dropped_counts: Mutex::new(HashMap::new()),
+            backlog_signal: Mutex::new(()),
+            backlog_changed: Condvar::new(),
+        }));
+
+        MsgQueue {
+            inner,
+            serial_no: 0,
+            running: false,
+        }
+    }
+
+    /// only can call before using `get_consumer` and `add_consumer`. `max_bytes`
+    /// bounds Dynamic mode's total live allocation: once a write would push it over
+    /// budget, the least-recently-read consumer's cursor is forced forward (losing
+    /// whatever it hadn't read yet) until the queue is back under budget, so a slow or
+    /// dead consumer can't grow it without limit. Pass `u64::MAX` for no bound.
+    pub fn set_dynamic(&mut self, block_length: u64, max_bytes: u64) {
+        if self.running {
             panic!("Rb is running, please config before running");
-            return;
         }
-        (*self.inner).borrow_mut().set_dynamic(block_length);
+        self.inner.write().unwrap().set_dynamic(block_length, max_bytes);
     }
 
     pub fn set_fixed(&mut self, block_length: u64, buf_size: u64) {
-        if self.running == true {
+        if self.running {
+            panic!("Rb is running, please config before running");
+        }
+        self.inner.write().unwrap().set_fixed(block_length, buf_size);
+    }
+
+    /// switches to priority-ordered delivery: each consumer's `read`/`read_all`/`size`
+    /// drains its own backlog in descending priority (ties broken by arrival order)
+    /// instead of FIFO. Only `MsgQueueWriter::write_with_priority` produces data once
+    /// this is set — the plain `write` panics, since it has no priority to assign.
+    pub fn set_priority_mode(&mut self) {
+        if self.running {
             panic!("Rb is running, please config before running");
-            return;
         }
-        (*self.inner).borrow_mut().set_fixed(block_length, buf_size);
+        self.inner.write().unwrap().set_priority_mode();
+    }
+
+    /// configures backpressure for one consumer: once `id`'s unread backlog would
+    /// exceed `cap` after a `write`, `policy` decides what happens to it. See
+    /// `ConsumerOverflowPolicy`. Unlike `set_fixed`/`set_dynamic`/`set_priority_mode`,
+    /// callable at any time — it doesn't reconfigure the ring, just registers a
+    /// per-consumer limit `write_with_backpressure` checks against.
+    pub fn set_consumer_capacity(&mut self, id: u64, cap: u64, policy: ConsumerOverflowPolicy) {
+        self.inner.read().unwrap().set_consumer_capacity(id, cap, policy);
     }
 
     pub fn add_producer(&mut self) -> MsgQueueWriter<T> {
@@ -69,14 +154,15 @@ where
         }
     }
 
-    /// get_consumer won't panic even buffer block doesn't exist,
-    /// system will check the matched block and create it when it doesn't exist.
+    /// get_consumer won't panic even the reader isn't registered yet: it registers a
+    /// cursor for `id` against the shared ring, starting from whatever's already been
+    /// written so far, the same as `add_consumer` does for an auto-assigned id.
     pub fn get_consumer(&mut self, id: u64) -> MsgQueueReader<T> {
         if self.running == false {
             self.running = true;
         }
 
-        let mut buf = (*self.inner).borrow_mut();
+        let mut buf = self.inner.write().unwrap();
         buf.add_buffer_cache(id);
         MsgQueueReader {
             id,
@@ -92,7 +178,7 @@ where
 
         let id = self.serial_no;
         self.serial_no += 1;
-        let mut buf = (*self.inner).borrow_mut();
+        let mut buf = self.inner.write().unwrap();
         buf.add_buffer_cache(id);
         MsgQueueReader {
             id,
@@ -100,488 +186,1898 @@ where
         }
     }
 
-    pub fn get_consumer_count(&self) -> u64 {
-        (*self.inner).borrow().buf.len() as u64
+    pub fn get_consumer_count(&self) -> u64 {
+        self.inner.read().unwrap().ring.reader_count()
+    }
+
+    pub fn delete_consumer(&mut self, id: u64) {
+        self.inner.write().unwrap().delete_buffer_cache(id)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// total bytes currently allocated by the shared ring. Dynamic and Priority modes'
+    /// figures vary with the live backlog; Fixed mode always reports its flat slab's size.
+    pub fn current_bytes(&self) -> u64 {
+        self.inner.read().unwrap().ring.current_bytes()
+    }
+
+    /// how many elements Dynamic mode's byte budget has forced out of a lagging
+    /// consumer's unread backlog. Always `0` in Fixed mode.
+    pub fn evicted_count(&self) -> u64 {
+        self.inner.read().unwrap().ring.evicted_count()
+    }
+
+    /// register a named subscription; calling it again for an existing name is a no-op.
+    pub fn set_subscription(&mut self, name: String) {
+        self.inner.write().unwrap().add_subscription(name);
+    }
+
+    /// register a named subscription backed by a fixed-capacity ring.
+    /// `capacity` is in elements; what happens once it's exceeded is controlled by
+    /// `Control::set_overflow_policy` (defaults to `OverflowPolicy::OverwriteOldest`).
+    pub fn set_subscription_with_capacity(&mut self, name: String, capacity: u64) {
+        self.inner
+            .write()
+            .unwrap()
+            .add_subscription_with_capacity(name, capacity);
+    }
+
+    /// fetch the `Control` handle for a previously registered subscription name.
+    pub fn get_subscription(&mut self, name: String) -> Result<Control<T>, String> {
+        let buf = self.inner.read().unwrap();
+        match buf.subscriptions.get(&name) {
+            Some(sub) => Ok(Control {
+                name,
+                inner: sub.clone(),
+            }),
+            None => Err(format!("subscription not found: {}", name)),
+        }
+    }
+}
+
+impl<T> MsgQueue<T> {
+    /// the dedicated single-producer/single-consumer fast path: instead of a
+    /// `MsgQueue` plus `add_producer`/`add_consumer`, returns a bound
+    /// `SpscProducer`/`SpscConsumer` pair sharing one fixed-capacity ring directly —
+    /// there's no `MsgQueueInner` and no `RwLock` underneath them at all, so this
+    /// intentionally isn't a method that hands back `Self`: anything reachable
+    /// through `MsgQueue`'s other methods (subscriptions, dynamic-mode reconfig) goes
+    /// through that lock, which is exactly what the SPSC path exists to avoid. Reach
+    /// for this when you know up front you have exactly one producer and one
+    /// consumer and want wait-free enqueue/dequeue; reach for `new()` plus
+    /// `set_fixed`/`set_dynamic`/`set_priority_mode` for everything else.
+    pub fn new_spsc(capacity: usize) -> (SpscProducer<T>, SpscConsumer<T>) {
+        let ring = Arc::new(SpscRing::new(capacity));
+        (
+            SpscProducer { ring: ring.clone() },
+            SpscConsumer { ring },
+        )
+    }
+}
+
+struct MsgQueueInner<T> {
+    ring: BufferCache<T>,
+    subscriptions: HashMap<String, Arc<Mutex<Subscription<T>>>>,
+    mode: Option<BufferCacheMode>,
+    buf_size: u64,
+    block_length: u64,
+    /// per-consumer front-of-line redelivery queue: a message a dropped (uncommitted)
+    /// `ReceiveGuard` pushed back. Drained by `read_guarded` before it pulls a fresh
+    /// message from `ring` — `read`/`read_all`/`size` don't look at this queue, so
+    /// mixing plain reads with `read_guarded` on the same consumer isn't supported.
+    requeued: Mutex<HashMap<u64, VecDeque<T>>>,
+    /// count of messages currently checked out via a live, uncommitted `ReceiveGuard`
+    /// per consumer, for `MsgQueueReader::size_with_in_flight`.
+    in_flight: Mutex<HashMap<u64, u64>>,
+    /// per-consumer backpressure: `set_consumer_capacity` populates this, and every
+    /// `write` checks it for the registered consumers it names. See
+    /// `write_with_backpressure`.
+    consumer_limits: Mutex<HashMap<u64, (u64, ConsumerOverflowPolicy)>>,
+    /// elements `DropOldest`/`DropNewest` have discarded for a consumer, for
+    /// `MsgQueueReader::dropped_count`.
+    dropped_counts: Mutex<HashMap<u64, u64>>,
+    /// paired with `backlog_changed`: a `Block`-policy write parks the calling
+    /// thread here until some consumer's backlog drops back under its cap.
+    backlog_signal: Mutex<()>,
+    backlog_changed: Condvar,
+}
+
+impl<T> MsgQueueInner<T>
+where
+    T: Default + Clone,
+{
+    /// register `id` as a reader of the shared ring; a no-op if it's already registered.
+    pub fn add_buffer_cache(&mut self, id: u64) {
+        self.ring.add_reader(id);
+    }
+
+    pub fn delete_buffer_cache(&mut self, id: u64) {
+        self.ring.remove_reader(id);
+        self.requeued.lock().unwrap().remove(&id);
+        self.in_flight.lock().unwrap().remove(&id);
+        self.consumer_limits.lock().unwrap().remove(&id);
+        self.dropped_counts.lock().unwrap().remove(&id);
+    }
+
+    fn take_requeued(&self, reader_id: u64) -> Option<T> {
+        self.requeued
+            .lock()
+            .unwrap()
+            .get_mut(&reader_id)
+            .and_then(|queue| queue.pop_front())
+    }
+
+    fn requeue_front(&self, reader_id: u64, value: T) {
+        self.requeued
+            .lock()
+            .unwrap()
+            .entry(reader_id)
+            .or_insert_with(VecDeque::new)
+            .push_front(value);
+    }
+
+    fn requeued_len(&self, reader_id: u64) -> u64 {
+        self.requeued
+            .lock()
+            .unwrap()
+            .get(&reader_id)
+            .map_or(0, |queue| queue.len() as u64)
+    }
+
+    fn mark_in_flight(&self, reader_id: u64) {
+        *self.in_flight.lock().unwrap().entry(reader_id).or_insert(0) += 1;
+    }
+
+    fn clear_in_flight(&self, reader_id: u64) {
+        if let Some(count) = self.in_flight.lock().unwrap().get_mut(&reader_id) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    fn in_flight_count(&self, reader_id: u64) -> u64 {
+        *self.in_flight.lock().unwrap().get(&reader_id).unwrap_or(&0)
+    }
+
+    /// registers a backpressure cap/policy for `id`. Only takes effect once `id` is
+    /// a registered reader (via `get_consumer`/`add_consumer`) — set ahead of time
+    /// and it just sits there inert until then, same as calling it before the
+    /// consumer exists at all.
+    pub fn set_consumer_capacity(&self, id: u64, cap: u64, policy: ConsumerOverflowPolicy) {
+        self.consumer_limits.lock().unwrap().insert(id, (cap, policy));
+    }
+
+    fn record_dropped(&self, id: u64, n: u64) {
+        if n > 0 {
+            *self.dropped_counts.lock().unwrap().entry(id).or_insert(0) += n;
+        }
+    }
+
+    fn dropped_count(&self, id: u64) -> u64 {
+        *self.dropped_counts.lock().unwrap().get(&id).unwrap_or(&0)
+    }
+
+    /// enforces every registered consumer's `set_consumer_capacity` policy around a
+    /// single shared `write`. `Error` consumers are checked before anything is
+    /// written, so a rejected write touches nothing. `Block` consumers park this
+    /// call on `backlog_changed` until they've drained below cap. Once the data
+    /// actually lands, any `DropOldest`/`DropNewest` consumer left over cap gets
+    /// trimmed back down — `DropOldest` discards from the front of its own unread
+    /// backlog (the same direction a lagging Fixed-mode reader already loses data
+    /// in), `DropNewest` jumps its cursor straight past the batch that was just
+    /// written, so it never sees this batch at all. Either way only that one
+    /// consumer's cursor moves; the shared ring itself, and every other consumer's
+    /// view of it, is untouched.
+    ///
+    /// Fixed/Dynamic modes only — `MsgQueueWriter::write_with_priority` doesn't call
+    /// this, since Priority mode's per-reader heaps don't share this method's
+    /// single-shared-copy-plus-cursor model to begin with.
+    fn write_with_backpressure(&self, data: Vec<T>) -> Result<(), ConsumerSaturated> {
+        let added = data.len() as u64;
+
+        loop {
+            let limits = self.consumer_limits.lock().unwrap();
+            for (&id, &(cap, policy)) in limits.iter() {
+                if !self.ring.has_reader(id) {
+                    continue;
+                }
+                if policy == ConsumerOverflowPolicy::Error
+                    && self.ring.size_for(id) + added > cap
+                {
+                    return Err(ConsumerSaturated { consumer_id: id, cap });
+                }
+            }
+            let still_blocked = limits.iter().any(|(&id, &(cap, policy))| {
+                policy == ConsumerOverflowPolicy::Block
+                    && self.ring.has_reader(id)
+                    && self.ring.size_for(id) + added > cap
+            });
+            drop(limits);
+            if !still_blocked {
+                break;
+            }
+            let guard = self.backlog_signal.lock().unwrap();
+            let _ = self
+                .backlog_changed
+                .wait_timeout(guard, std::time::Duration::from_millis(50));
+        }
+
+        self.ring.write(data);
+
+        let limits = self.consumer_limits.lock().unwrap();
+        for (&id, &(cap, policy)) in limits.iter() {
+            if !self.ring.has_reader(id) {
+                continue;
+            }
+            let backlog = self.ring.size_for(id);
+            if backlog <= cap {
+                continue;
+            }
+            match policy {
+                ConsumerOverflowPolicy::DropOldest => {
+                    let excess = backlog - cap;
+                    self.ring.read_for(id, excess);
+                    self.record_dropped(id, excess);
+                }
+                ConsumerOverflowPolicy::DropNewest => {
+                    let skip = added.min(backlog);
+                    self.ring.skip_newest_for(id, skip);
+                    self.record_dropped(id, skip);
+                }
+                ConsumerOverflowPolicy::Block | ConsumerOverflowPolicy::Error => {}
+            }
+        }
+        drop(limits);
+        self.backlog_changed.notify_all();
+        Ok(())
+    }
+
+    pub fn set_dynamic(&mut self, block_length: u64, max_bytes: u64) {
+        self.mode = Some(Dynamic);
+        self.block_length = block_length;
+        self.ring.set_dynamic_mode(block_length, max_bytes);
+    }
+
+    pub fn set_fixed(&mut self, block_length: u64, buf_size: u64) {
+        self.mode = Some(Fixed);
+        self.block_length = block_length;
+        self.buf_size = buf_size;
+        self.ring.set_fixed_mode(buf_size, block_length);
+    }
+
+    pub fn set_priority_mode(&mut self) {
+        self.mode = Some(Priority);
+        self.ring.set_priority_mode();
+    }
+
+    pub fn add_subscription(&mut self, name: String) {
+        if !self.subscriptions.contains_key(&name) {
+            self.subscriptions.insert(
+                name.clone(),
+                Arc::new(Mutex::new(Subscription::new(name, None))),
+            );
+        }
+    }
+
+    pub fn add_subscription_with_capacity(&mut self, name: String, capacity: u64) {
+        if !self.subscriptions.contains_key(&name) {
+            self.subscriptions.insert(
+                name.clone(),
+                Arc::new(Mutex::new(Subscription::new(name, Some(capacity)))),
+            );
+        }
+    }
+}
+
+/// what a ring-bounded `Subscription` should do when `push_data` would exceed `capacity`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OverflowPolicy {
+    /// drop the oldest buffered bytes and advance the read cursor to make room.
+    OverwriteOldest,
+    /// keep the existing data and reject the bytes that don't fit.
+    RejectNew,
+    /// park the producer until a reader frees up enough space.
+    Block,
+}
+
+/// error returned by `Control::push_data` under `OverflowPolicy::RejectNew`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedPush {
+    pub rejected_len: u64,
+}
+
+/// what `MsgQueueWriter::write` should do, for a given consumer, once
+/// `MsgQueue::set_consumer_capacity` says its unread backlog would exceed its cap.
+/// Unlike `OverflowPolicy` (one named subscription, one capacity), each consumer of
+/// the anonymous producer/consumer API gets its own independent cap and policy, set
+/// through the shared `MsgQueue` rather than a per-consumer handle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConsumerOverflowPolicy {
+    /// evict this consumer's oldest unread elements to make room; other consumers
+    /// are unaffected.
+    DropOldest,
+    /// discard the incoming write for this consumer only; other consumers still see it.
+    DropNewest,
+    /// park the producer's `write` until this consumer's backlog drops under cap.
+    Block,
+    /// reject the whole write and report which consumer is saturated.
+    Error,
+}
+
+/// error returned by `MsgQueueWriter::write` when an `Error`-policy consumer's
+/// capacity would be exceeded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsumerSaturated {
+    pub consumer_id: u64,
+    pub cap: u64,
+}
+
+/// backing storage for a single named subscription.
+///
+/// Unbounded (`capacity: None`) by default; `MsgQueue::set_subscription_with_capacity`
+/// turns it into a fixed-capacity ring governed by `Control::set_overflow_policy`.
+///
+/// Data is retained until every registered cursor (the implicit default one used by
+/// `Control::read`/`read_all`, plus any added with `Control::add_reader`) has consumed
+/// it; `base_offset` is the absolute index of `data[0]` once the minimum cursor has
+/// reclaimed everything before it.
+struct Subscription<T> {
+    name: String,
+    data: Vec<T>,
+    base_offset: u64,
+    next_reader_id: u64,
+    cursors: HashMap<u64, u64>,
+    capacity: Option<u64>,
+    overflow_policy: OverflowPolicy,
+    wakers: Vec<Waker>,
+    producer_wakers: Vec<Waker>,
+}
+
+/// the id used for the cursor implicitly owned by the `Control` handle itself.
+const DEFAULT_READER_ID: u64 = 0;
+
+impl<T> Subscription<T> {
+    fn new(name: String, capacity: Option<u64>) -> Subscription<T> {
+        let mut cursors = HashMap::new();
+        cursors.insert(DEFAULT_READER_ID, 0);
+        Subscription {
+            name,
+            data: Vec::new(),
+            base_offset: 0,
+            next_reader_id: DEFAULT_READER_ID + 1,
+            cursors,
+            capacity,
+            overflow_policy: OverflowPolicy::OverwriteOldest,
+            wakers: Vec::new(),
+            producer_wakers: Vec::new(),
+        }
+    }
+
+    fn write_offset(&self) -> u64 {
+        self.base_offset + self.data.len() as u64
+    }
+
+    fn cursor_size(&self, reader_id: u64) -> u64 {
+        self.write_offset() - self.cursors[&reader_id]
+    }
+
+    /// total live (unreclaimed) size, i.e. how much the slowest reader still owes.
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn remaining(&self) -> u64 {
+        match self.capacity {
+            Some(cap) => cap.saturating_sub(self.size()),
+            None => u64::MAX,
+        }
+    }
+
+    fn min_cursor(&self) -> u64 {
+        self.cursors
+            .values()
+            .copied()
+            .min()
+            .unwrap_or_else(|| self.write_offset())
+    }
+
+    /// drop bytes every registered cursor has already consumed.
+    fn reclaim(&mut self) {
+        let min = self.min_cursor();
+        let drop_n = (min - self.base_offset) as usize;
+        if drop_n > 0 {
+            self.data.drain(0..drop_n);
+            self.base_offset += drop_n as u64;
+        }
+    }
+
+    fn read_for(&mut self, reader_id: u64, size: u64) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let available = self.cursor_size(reader_id);
+        let take = size.min(available);
+        let cursor = self.cursors[&reader_id];
+        let start = (cursor - self.base_offset) as usize;
+        let end = start + take as usize;
+        let res = self.data[start..end].to_vec();
+        *self.cursors.get_mut(&reader_id).unwrap() += take;
+        if take > 0 {
+            self.reclaim();
+            self.wake_producers();
+        }
+        res
+    }
+
+    fn add_reader(&mut self) -> u64 {
+        let id = self.next_reader_id;
+        self.next_reader_id += 1;
+        self.cursors.insert(id, self.write_offset());
+        id
+    }
+
+    fn remove_reader(&mut self, reader_id: u64) {
+        self.cursors.remove(&reader_id);
+        self.reclaim();
+        self.wake_producers();
+    }
+
+    fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn wake_producers(&mut self) {
+        for waker in self.producer_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// a handle to a named subscription, returned by `MsgQueue::get_subscription`.
+///
+/// `Control` also implements `futures_core::Stream`, so a consumer can do
+/// `while let Some(chunk) = subscription.next().await` instead of polling `size()`.
+pub struct Control<T> {
+    name: String,
+    inner: Arc<Mutex<Subscription<T>>>,
+}
+
+impl<T> Control<T>
+where
+    T: Default + Clone,
+{
+    pub fn print_hello(&self) {
+        println!("hello from subscription: {}", self.name);
+    }
+
+    pub fn subscription_name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// whether the backing subscription is still registered in its `MsgQueue`.
+    pub fn is_exist(&self) -> bool {
+        true
+    }
+
+    /// push data onto the subscription, honouring its `OverflowPolicy` if it has a capacity.
+    /// Unbounded subscriptions (the default) always succeed.
+    pub fn push_data(&mut self, data: Vec<T>) -> Result<(), RejectedPush> {
+        let mut sub = (*self.inner).lock().unwrap();
+        Self::apply_overwrite_or_reject(&mut sub, data)
+    }
+
+    /// the `OverwriteOldest`/`RejectNew` halves of `push_data`'s overflow handling,
+    /// shared with `push_data_async` (which only needs its own poll loop for `Block`).
+    fn apply_overwrite_or_reject(
+        sub: &mut Subscription<T>,
+        mut data: Vec<T>,
+    ) -> Result<(), RejectedPush> {
+        let overflow = (data.len() as u64).saturating_sub(sub.remaining());
+        if overflow > 0 {
+            match sub.overflow_policy {
+                OverflowPolicy::OverwriteOldest => {
+                    // append first: target has to be sized against the *post-append*
+                    // write_offset, not just overflow relative to what's currently
+                    // resident. A batch bigger than the whole configured capacity has
+                    // overflow > sub.data.len(), so forcing cursors to min_cursor() +
+                    // overflow and reclaiming before the append asks `reclaim` to
+                    // drain more bytes than `sub.data` has, panicking. Forcing every
+                    // cursor to `write_offset() - capacity` after the append keeps at
+                    // most `capacity` bytes - the most recent ones - regardless of how
+                    // far over capacity the incoming batch is.
+                    sub.data.append(&mut data);
+                    let target = sub.write_offset().saturating_sub(sub.capacity.unwrap());
+                    for cursor in sub.cursors.values_mut() {
+                        if *cursor < target {
+                            *cursor = target;
+                        }
+                    }
+                    sub.reclaim();
+                    sub.wake_all();
+                    return Ok(());
+                }
+                OverflowPolicy::RejectNew => {
+                    let fits = (data.len() as u64 - overflow) as usize;
+                    data.truncate(fits);
+                    sub.data.append(&mut data);
+                    sub.wake_all();
+                    return Err(RejectedPush {
+                        rejected_len: overflow,
+                    });
+                }
+                OverflowPolicy::Block => {
+                    // synchronous push_data can't park; callers that configured `Block`
+                    // should drive space with `push_data_async` instead.
+                    return Err(RejectedPush {
+                        rejected_len: overflow,
+                    });
+                }
+            }
+        }
+        sub.data.append(&mut data);
+        sub.wake_all();
+        Ok(())
+    }
+
+    /// like `push_data`, but under `OverflowPolicy::Block` this awaits until a reader
+    /// frees up enough space instead of failing; `OverwriteOldest`/`RejectNew` resolve
+    /// immediately with the exact same semantics `push_data` has for them.
+    pub async fn push_data_async(&mut self, data: Vec<T>) -> Result<(), RejectedPush> {
+        let mut data = Some(data);
+        std::future::poll_fn(move |cx| {
+            let mut sub = (*self.inner).lock().unwrap();
+            if sub.overflow_policy == OverflowPolicy::Block
+                && (data.as_ref().unwrap().len() as u64) > sub.remaining()
+            {
+                sub.producer_wakers.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+            Poll::Ready(Self::apply_overwrite_or_reject(
+                &mut sub,
+                data.take().unwrap(),
+            ))
+        })
+        .await
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        (*self.inner).lock().unwrap().overflow_policy = policy;
+    }
+
+    /// total capacity in elements, or `None` if the subscription is unbounded.
+    pub fn capacity(&self) -> Option<u64> {
+        (*self.inner).lock().unwrap().capacity
+    }
+
+    /// free space in elements, or `u64::MAX` if the subscription is unbounded.
+    pub fn remaining(&self) -> u64 {
+        (*self.inner).lock().unwrap().remaining()
+    }
+
+    /// unread size for this `Control`'s own (default) cursor.
+    pub fn size(&self) -> u64 {
+        (*self.inner).lock().unwrap().cursor_size(DEFAULT_READER_ID)
+    }
+
+    pub fn read(&mut self, size: u64) -> Vec<T> {
+        (*self.inner)
+            .lock()
+            .unwrap()
+            .read_for(DEFAULT_READER_ID, size)
+    }
+
+    pub fn read_all(&mut self) -> Vec<T> {
+        let size = self.size();
+        self.read(size)
+    }
+
+    /// whether there is any unread data available right now.
+    pub fn readable(&self) -> bool {
+        self.size() != 0
+    }
+
+    /// register an independent reader cursor on this subscription for true fan-out:
+    /// each `ReaderHandle` (and the `Control` itself) sees the same data and can
+    /// consume it at its own pace; data is retained until every cursor has read it.
+    pub fn add_reader(&self) -> ReaderHandle<T> {
+        let id = (*self.inner).lock().unwrap().add_reader();
+        ReaderHandle {
+            id,
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// an independent read cursor on a subscription, created with `Control::add_reader`.
+pub struct ReaderHandle<T> {
+    id: u64,
+    inner: Arc<Mutex<Subscription<T>>>,
+}
+
+impl<T> ReaderHandle<T>
+where
+    T: Default + Clone,
+{
+    pub fn size(&self) -> u64 {
+        (*self.inner).lock().unwrap().cursor_size(self.id)
+    }
+
+    pub fn read(&mut self, size: u64) -> Vec<T> {
+        (*self.inner).lock().unwrap().read_for(self.id, size)
+    }
+
+    pub fn read_all(&mut self) -> Vec<T> {
+        let size = self.size();
+        self.read(size)
+    }
+}
+
+impl<T> Drop for ReaderHandle<T> {
+    fn drop(&mut self) {
+        (*self.inner).lock().unwrap().remove_reader(self.id);
+    }
+}
+
+/// on-disk snapshot format: `b"RBUF"` magic, a u32 little-endian version, then each
+/// subscription's name, cursors and live bytes. Only `MsgQueue<u8>` is persistable
+/// today, since the format stores raw bytes rather than arbitrary `T`.
+const SNAPSHOT_MAGIC: &[u8; 4] = b"RBUF";
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// a tiny bounds-checked cursor over a byte slice, used only by `load_from_path`.
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.pos + len > self.bytes.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated ringbuf snapshot",
+            ));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+impl MsgQueue<u8> {
+    /// serializes every subscription (name, cursors, and live buffered bytes) to `path`
+    /// in one shot via `fs::write`.
+    pub fn save_to_path<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let inner = self.inner.read().unwrap();
+        let mut out = Vec::new();
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(inner.subscriptions.len() as u32).to_le_bytes());
+        for (name, sub) in inner.subscriptions.iter() {
+            let sub = sub.lock().unwrap();
+            let name_bytes = name.as_bytes();
+            out.extend_from_slice(&(name_bytes.len() as u32).to_le_bytes());
+            out.extend_from_slice(name_bytes);
+            out.extend_from_slice(&sub.base_offset.to_le_bytes());
+            out.extend_from_slice(&(sub.data.len() as u64).to_le_bytes());
+            out.extend_from_slice(&sub.data);
+            out.extend_from_slice(&(sub.cursors.len() as u32).to_le_bytes());
+            for (&reader_id, &offset) in sub.cursors.iter() {
+                out.extend_from_slice(&reader_id.to_le_bytes());
+                out.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+        fs::write(path, out)
+    }
+
+    /// restores a queue previously written by `save_to_path`. Rejects truncated
+    /// files and files whose version doesn't match `SNAPSHOT_VERSION`.
+    pub fn load_from_path<P: AsRef<Path>>(path: P) -> io::Result<MsgQueue<u8>> {
+        let bytes = fs::read(path)?;
+        // allocate the read buffer to the file size up front, matching the one-shot
+        // fs::read/fs::write round trip rather than incremental reads.
+        let mut reader = ByteReader::new(&bytes);
+        let magic = reader.take(4)?;
+        if magic != SNAPSHOT_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a ringbuf snapshot (bad magic)",
+            ));
+        }
+        let version = reader.u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported ringbuf snapshot version {} (expected {})",
+                    version, SNAPSHOT_VERSION
+                ),
+            ));
+        }
+        let mut queue = MsgQueue::new();
+        let subscription_count = reader.u32()?;
+        for _ in 0..subscription_count {
+            let name_len = reader.u32()? as usize;
+            let name = String::from_utf8(reader.take(name_len)?.to_vec()).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "subscription name is not valid UTF-8")
+            })?;
+            let base_offset = reader.u64()?;
+            let data_len = reader.u64()? as usize;
+            let data = reader.take(data_len)?.to_vec();
+            let cursor_count = reader.u32()?;
+            let mut cursors = HashMap::new();
+            let mut next_reader_id = DEFAULT_READER_ID + 1;
+            for _ in 0..cursor_count {
+                let reader_id = reader.u64()?;
+                let offset = reader.u64()?;
+                cursors.insert(reader_id, offset);
+                if reader_id >= next_reader_id {
+                    next_reader_id = reader_id + 1;
+                }
+            }
+            let sub = Subscription {
+                name: name.clone(),
+                data,
+                base_offset,
+                next_reader_id,
+                cursors,
+                capacity: None,
+                overflow_policy: OverflowPolicy::OverwriteOldest,
+                wakers: Vec::new(),
+                producer_wakers: Vec::new(),
+            };
+            queue
+                .inner
+                .write()
+                .unwrap()
+                .subscriptions
+                .insert(name, Arc::new(Mutex::new(sub)));
+        }
+        Ok(queue)
+    }
+}
+
+impl<T> Stream for Control<T>
+where
+    T: Default + Clone,
+{
+    type Item = Vec<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.size() == 0 {
+            (*this.inner).lock().unwrap().wakers.push(cx.waker().clone());
+            return Poll::Pending;
+        }
+        Poll::Ready(Some(this.read_all()))
+    }
+}
+
+/// for data reader
+pub struct MsgQueueReader<T> {
+    id: u64,
+    inner: Arc<RwLock<MsgQueueInner<T>>>,
+}
+
+/// for data writer
+pub struct MsgQueueWriter<T> {
+    inner: Arc<RwLock<MsgQueueInner<T>>>,
+}
+
+// `MsgQueueInner::subscriptions` holds `Arc<Mutex<Subscription<T>>>` rather than
+// `Rc<RefCell<..>>` precisely so these impls are genuinely true rather than merely
+// asserted: every path reachable through a shared `MsgQueueReader`/`MsgQueueWriter` (the
+// `ring`/`BufferCache` path, synchronized by its own internal locks and atomic tail,
+// plus the `Mutex`-guarded named-subscription map)
+// is safe to touch from multiple threads at once. Mirrors `MsgQueue<T>`'s own impls.
+unsafe impl<T: Send> Send for MsgQueueReader<T> {}
+unsafe impl<T: Send> Send for MsgQueueWriter<T> {}
+unsafe impl<T: Send> Sync for MsgQueueWriter<T> {}
+
+impl<T> MsgQueueReader<T>
+where
+    T: Default + Clone,
+{
+    /// every consumer reads from the one shared ring through its own cursor, so
+    /// broadcasting to N consumers costs one write and N cursor bumps rather than N
+    /// clones of the data. Only takes a read lock on the consumer map; advancing this
+    /// reader's own cursor goes through the ring's cursor mutex, which every reader
+    /// shares, so concurrent reads serialize briefly on that.
+    pub fn read(&mut self, size: u64) -> Vec<T> {
+        let buf = self.inner.read().unwrap();
+        buf.ring.read_for(self.id, size)
+    }
+    pub fn read_all(&mut self) -> Vec<T> {
+        let size = self.size();
+        self.read(size)
+    }
+    pub fn size(&mut self) -> u64 {
+        let buf = self.inner.read().unwrap();
+        buf.ring.size_for(self.id)
+    }
+
+    /// equivalent of `read(length)`, returning the readable region as at most two owned
+    /// `Vec`s (see `BufferCache::peek_slices_for`) instead of one `Vec`, and
+    /// advancing this reader's cursor past whatever it returns. `T = u8` is the
+    /// intended use: pass the pair straight to `std::io::IoSlice`s for
+    /// `write_vectored`. Fixed mode only.
+    ///
+    /// clones rather than borrows: `add_producer` allows any number of concurrent
+    /// `MsgQueueWriter`s, serialized against each other only by `write_fixed`'s
+    /// internal `write_lock` — the outer read lock doesn't exclude them, since writers
+    /// hold it too — so a borrowed slice into the slab could alias a concurrent
+    /// writer's in-place mutation of the same storage the instant the ring wraps a
+    /// full lap past it. Cloning into owned `Vec`s while holding `write_lock` keeps
+    /// every writer out of the slab for the duration of the copy.
+    pub fn read_slices(&mut self, length: u64) -> (Vec<T>, Vec<T>) {
+        let buf = self.inner.read().unwrap();
+        let _writers = buf.ring.write_lock.lock().unwrap();
+        let (a, b) = buf.ring.read_slices_for(self.id, length);
+        (a.to_vec(), b.to_vec())
+    }
+
+    /// like `read_slices`, but doesn't advance the cursor — pair with `consume` to
+    /// retry a partial `write_vectored` without losing data on a short write.
+    pub fn peek_slices(&self, length: u64) -> (Vec<T>, Vec<T>) {
+        let buf = self.inner.read().unwrap();
+        let _writers = buf.ring.write_lock.lock().unwrap();
+        let (a, b) = buf.ring.peek_slices_for(self.id, length);
+        (a.to_vec(), b.to_vec())
+    }
+
+    /// commits `n` elements previously returned by `peek_slices`.
+    pub fn consume(&mut self, n: u64) {
+        let buf = self.inner.read().unwrap();
+        buf.ring.consume(self.id, n);
+    }
+
+    /// Priority mode only: the next element this reader's `read`/`read_all` would
+    /// return, without removing it. See `BufferCache::peek_highest` for why this
+    /// clones rather than borrowing.
+    pub fn peek_highest(&self) -> Option<T> {
+        let buf = self.inner.read().unwrap();
+        buf.ring.peek_highest(self.id)
+    }
+
+    /// at-least-once delivery: returns a `ReceiveGuard` wrapping the next element
+    /// instead of handing it over outright. Commit the guard once it's been durably
+    /// handled; if it's dropped uncommitted (an error return, a panic unwinding
+    /// through it), the element is pushed back to the front of this reader's queue so
+    /// a later `read_guarded` sees it again. Mode-agnostic — works the same under
+    /// Fixed, Dynamic or Priority. A fresh element is taken through the same cursor
+    /// `read`/`read_all`/`size` use, but those don't look at the redelivery queue, so
+    /// mixing plain reads with `read_guarded` on the same consumer isn't supported:
+    /// a plain `read` can't see what's sitting in the redelivery queue.
+    pub fn read_guarded(&mut self) -> Option<ReceiveGuard<T>> {
+        let value = {
+            let buf = self.inner.read().unwrap();
+            match buf.take_requeued(self.id) {
+                Some(value) => Some(value),
+                None => buf.ring.read_for(self.id, 1).into_iter().next(),
+            }
+        };
+        let value = value?;
+        self.inner.read().unwrap().mark_in_flight(self.id);
+        Some(ReceiveGuard {
+            value: Some(value),
+            reader_id: self.id,
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// `size()` plus, when `include_in_flight` is set, the messages currently
+    /// checked out via a live `ReceiveGuard` or sitting in the redelivery queue
+    /// waiting on a `read_guarded` to pick them back up — i.e. everything not yet
+    /// permanently resolved, not just what's still sitting in the ring.
+    pub fn size_with_in_flight(&mut self, include_in_flight: bool) -> u64 {
+        let size = self.size();
+        if !include_in_flight {
+            return size;
+        }
+        let buf = self.inner.read().unwrap();
+        size + buf.in_flight_count(self.id) + buf.requeued_len(self.id)
+    }
+
+    /// how many elements `set_consumer_capacity`'s `DropOldest`/`DropNewest` policy
+    /// has discarded for this consumer so far, for detecting a lossy consumer.
+    pub fn dropped_count(&self) -> u64 {
+        self.inner.read().unwrap().dropped_count(self.id)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+/// a single element checked out via `MsgQueueReader::read_guarded`. Dropping it
+/// without calling `commit` puts the element back for another `read_guarded` to
+/// pick up — the classic `Option`-field-plus-`take`-on-commit idiom: `commit` empties
+/// `value` so `Drop` sees `None` and does nothing but clear the in-flight count;
+/// otherwise `Drop` sees `Some` and requeues it.
+pub struct ReceiveGuard<T>
+where
+    T: Default + Clone,
+{
+    value: Option<T>,
+    reader_id: u64,
+    inner: Arc<RwLock<MsgQueueInner<T>>>,
+}
+
+impl<T> ReceiveGuard<T>
+where
+    T: Default + Clone,
+{
+    /// marks this element as durably handled: it will not be redelivered.
+    pub fn commit(mut self) {
+        self.value.take();
+    }
+}
+
+impl<T> std::ops::Deref for ReceiveGuard<T>
+where
+    T: Default + Clone,
+{
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("ReceiveGuard used after commit")
+    }
+}
+
+impl<T> std::ops::DerefMut for ReceiveGuard<T>
+where
+    T: Default + Clone,
+{
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("ReceiveGuard used after commit")
+    }
+}
+
+impl<T> Drop for ReceiveGuard<T>
+where
+    T: Default + Clone,
+{
+    fn drop(&mut self) {
+        let inner = self.inner.read().unwrap();
+        if let Some(value) = self.value.take() {
+            inner.requeue_front(self.reader_id, value);
+        }
+        inner.clear_in_flight(self.reader_id);
+    }
+}
+impl<T> MsgQueueWriter<T>
+where
+    T: Default + Clone,
+{
+    /// fans `data` out to every registered consumer. `Err` is only possible once
+    /// some consumer has an `ConsumerOverflowPolicy::Error` cap (via
+    /// `MsgQueue::set_consumer_capacity`) and this write would exceed it, in which
+    /// case nothing is written at all; an `Ok` write may still have been trimmed
+    /// back down for individual `DropOldest`/`DropNewest` consumers, or have parked
+    /// this call on a `Block` consumer's cap before landing. See
+    /// `MsgQueue::set_consumer_capacity`.
+    pub fn write(&self, data: Vec<T>) -> Result<(), ConsumerSaturated> {
+        self.inner.read().unwrap().write_with_backpressure(data)
+    }
+
+    /// like `write`, but for a `MsgQueue::with_buffer`-backed queue where silently
+    /// overwriting a slow reader's unconsumed data isn't acceptable: reports the
+    /// overflow instead, handing `data` back so the caller can retry once readers
+    /// catch up. See `BufferCache::try_write`. Fixed mode only.
+    pub fn try_write(&self, data: Vec<T>) -> Result<(), Vec<T>> {
+        self.inner.read().unwrap().ring.try_write(data)
+    }
+
+    /// Priority mode only: each `(item, priority)` pair is broadcast to every
+    /// registered consumer, who'll drain it in descending-priority order (ties
+    /// broken by arrival order) rather than the order `items` is passed in.
+    pub fn write_with_priority(&self, items: Vec<(T, u64)>) {
+        self.inner.read().unwrap().ring.write_with_priority(items);
+    }
+}
+
+// 64 bytes is the cache line size on the common x86_64/aarch64 targets this crate runs
+// on; padding `SpscRing`'s `head`/`tail` out to it keeps the producer's and consumer's
+// indices off the same cache line, so the two cores driving them don't bounce it back
+// and forth on every push/pop.
+#[repr(align(64))]
+struct CachePadded<U>(U);
+
+/// the dedicated single-producer/single-consumer fast path `MsgQueue::new_spsc`
+/// builds: a fixed-capacity circular buffer of `MaybeUninit<T>` slots with a
+/// producer-owned `tail` and consumer-owned `head`, each only ever written by their
+/// one owning side. Unlike `BufferCache`'s Fixed mode (one writer, many broadcast
+/// readers sharing `tail` under `Acquire`/`Release`), this is strictly one producer
+/// and one consumer, so `head` and `tail` never need to be read by the "wrong" side
+/// for anything but the full/empty check — no mutex and none of the `RwLock`
+/// `MsgQueueInner` is wrapped in; the two halves share the ring only through an `Arc`.
+struct SpscRing<T> {
+    slots: UnsafeCell<Box<[MaybeUninit<T>]>>,
+    capacity: usize,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+unsafe impl<T: Send> Sync for SpscRing<T> {}
+
+impl<T> SpscRing<T> {
+    fn new(capacity: usize) -> SpscRing<T> {
+        assert!(capacity > 0, "SPSC ring capacity must be non-zero");
+        let mut slots = Vec::with_capacity(capacity);
+        for _ in 0..capacity {
+            slots.push(MaybeUninit::uninit());
+        }
+        SpscRing {
+            slots: UnsafeCell::new(slots.into_boxed_slice()),
+            capacity,
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+}
+
+// only the producer ever advances `tail`, only the consumer ever advances `head`, and
+// a slot is only ever touched by whichever side currently owns it (the other side is
+// held off by the full/empty check), so drop order here mirrors a `VecDeque`'s: drop
+// exactly the initialized slots between `head` and `tail`, leave the rest alone.
+impl<T> Drop for SpscRing<T> {
+    fn drop(&mut self) {
+        let head = *self.head.0.get_mut();
+        let tail = *self.tail.0.get_mut();
+        let slots = self.slots.get_mut();
+        let mut idx = head;
+        while idx != tail {
+            unsafe {
+                slots[idx % self.capacity].assume_init_drop();
+            }
+            idx = idx.wrapping_add(1);
+        }
+    }
+}
+
+/// producer half of `MsgQueue::new_spsc`. Enqueue is wait-free: it never blocks, never
+/// takes a lock, and always completes in a bounded number of steps.
+pub struct SpscProducer<T> {
+    ring: Arc<SpscRing<T>>,
+}
+
+/// consumer half of `MsgQueue::new_spsc`. Dequeue is wait-free for the same reason
+/// enqueue is.
+pub struct SpscConsumer<T> {
+    ring: Arc<SpscRing<T>>,
+}
+
+unsafe impl<T: Send> Send for SpscProducer<T> {}
+unsafe impl<T: Send> Send for SpscConsumer<T> {}
+
+impl<T> SpscProducer<T> {
+    /// pushes `value` unless the ring is full, in which case it's handed straight
+    /// back so the caller can retry or drop it — there's nobody else to block on.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.ring.tail.0.load(Ordering::Relaxed);
+        let head = self.ring.head.0.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.ring.capacity {
+            return Err(value);
+        }
+        let idx = tail % self.ring.capacity;
+        unsafe {
+            (*self.ring.slots.get())[idx].write(value);
+        }
+        self.ring.tail.0.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// `true` once the matching `SpscConsumer` has been dropped — further `push`es
+    /// just fill a ring nothing will ever drain.
+    pub fn is_consumer_dropped(&self) -> bool {
+        Arc::strong_count(&self.ring) < 2
+    }
+}
+
+impl<T> SpscConsumer<T> {
+    /// pops the oldest pushed value, or `None` if the ring is currently empty.
+    pub fn pop(&self) -> Option<T> {
+        let head = self.ring.head.0.load(Ordering::Relaxed);
+        let tail = self.ring.tail.0.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let idx = head % self.ring.capacity;
+        let value = unsafe { (*self.ring.slots.get())[idx].assume_init_read() };
+        self.ring.head.0.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum BufferCacheMode {
+    Fixed,
+    Dynamic,
+    Priority,
+}
+
+/// `BufferCache<T>` is the single shared ring a `MsgQueue`'s producers write into and
+/// every consumer reads out of: each element is stored exactly once, and every
+/// consumer id owns a lightweight cursor into that one copy instead of getting a
+/// whole private buffer the element gets cloned into (the shrev-style event-channel
+/// model). In `Fixed` mode (the default) the element storage is a ring indexed by
+/// `tail`, an `AtomicU64` counter into a flat power-of-two `slots` region, published
+/// with a `Release` store the writer does after copying in; readers take an `Acquire`
+/// load of `tail` before reading, so the element writes happen-before a reader sees
+/// them. Writers are serialized among themselves by `write_lock`, and the per-reader
+/// cursors live behind a small `Mutex`
+/// since bumping one is not the hot path; a reader that falls more than `capacity()`
+/// behind has its cursor forced forward to the oldest still-live element on its next
+/// read, losing whatever it didn't keep up with — the same "coming data overlaps
+/// existing data" behaviour Fixed mode always had.
+///
+/// `Dynamic` mode never overwrites: it keeps every unreclaimed element in a growable
+/// `Vec` and reclaims the same way `Subscription` reclaims named-subscription data —
+/// elements behind every live cursor get dropped from the front — guarded by its own
+/// `Mutex` since reclaiming needs exclusive access regardless of how the outer
+/// consumer map is locked.
+///
+/// `Priority` mode trades the shared-copy model for a private `BinaryHeap` per reader:
+/// unlike Fixed/Dynamic, where a consumer's "next" element is a shared fact (the same
+/// suffix of one sequence), draining in priority order is consumer-specific — reader A
+/// popping its highest-priority element can't remove it for reader B, who's still
+/// waiting on it. So `write_with_priority` clones each item into every registered
+/// reader's own heap (the same per-consumer-copy cost the Fixed/Dynamic rewrite
+/// deliberately moved away from, but unavoidable here and fine since it's opt-in).
+struct BufferCache<T> {
+    mode: BufferCacheMode,
+    slots: UnsafeCell<Vec<T>>,
+    capacity_mask: u64,
+    tail: AtomicU64,
+    cursors: Mutex<HashMap<u64, u64>>,
+    /// Fixed mode only: see `skip_newest_for` — while `reader_id` has an entry here and
+    /// hasn't yet read up to it, reads/`size_for` act as if `tail` stopped at that
+    /// position instead of wherever the ring's real tail has moved on to.
+    newest_ceiling: Mutex<HashMap<u64, u64>>,
+    /// Fixed mode only: `MsgQueue::add_producer` can be called any number of times,
+    /// so `write_fixed`'s `&mut` into `slots` is only sound with at most one writer in
+    /// there at a time — this serializes them. Genuinely contended, lock-free
+    /// single-producer/single-consumer use should reach for `MsgQueue::new_spsc`
+    /// instead, which has no shared writer (or this lock) to begin with.
+    write_lock: Mutex<()>,
+    dynamic: Mutex<DynamicState<T>>,
+    priority: Mutex<PriorityState<T>>,
+}
+
+unsafe impl<T: Send> Sync for BufferCache<T> {}
+
+impl<T> BufferCache<T>
+where
+    T: Default + Clone,
+{
+    pub fn new() -> BufferCache<T> {
+        let capacity = 8192u64; //default: two 4096-element pages, same as before
+        let mut cursors = HashMap::new();
+        cursors.insert(DEFAULT_READER_ID, 0);
+        BufferCache {
+            mode: Fixed,
+            slots: UnsafeCell::new(vec![T::default(); capacity as usize]),
+            capacity_mask: capacity - 1,
+            tail: AtomicU64::new(0),
+            cursors: Mutex::new(cursors),
+            newest_ceiling: Mutex::new(HashMap::new()),
+            write_lock: Mutex::new(()),
+            dynamic: Mutex::new(DynamicState::new(4096, None)),
+            priority: Mutex::new(PriorityState::new()),
+        }
+    }
+
+    /// builds a Fixed-mode ring directly out of caller-supplied storage instead of
+    /// `new`'s internally allocated `Vec` — see `MsgQueue::with_buffer`.
+    /// `storage.len()` must already be a power of two, the same invariant
+    /// `set_fixed_mode` maintains by rounding up; panics otherwise, since there's no
+    /// spare capacity here to round up into.
+    pub fn with_buffer(storage: Vec<T>) -> BufferCache<T> {
+        assert!(
+            storage.len().is_power_of_two(),
+            "with_buffer requires a power-of-two length, got {}",
+            storage.len()
+        );
+        let capacity = storage.len() as u64;
+        let mut cursors = HashMap::new();
+        cursors.insert(DEFAULT_READER_ID, 0);
+        BufferCache {
+            mode: Fixed,
+            slots: UnsafeCell::new(storage),
+            capacity_mask: capacity - 1,
+            tail: AtomicU64::new(0),
+            cursors: Mutex::new(cursors),
+            newest_ceiling: Mutex::new(HashMap::new()),
+            write_lock: Mutex::new(()),
+            dynamic: Mutex::new(DynamicState::new(4096, None)),
+            priority: Mutex::new(PriorityState::new()),
+        }
+    }
+
+    fn ring_capacity(&self) -> u64 {
+        self.capacity_mask + 1
+    }
+
+    /// Fixed mode only: what `reader_id` should treat as `tail` right now. Equal to the
+    /// real `tail` unless `skip_newest_for` capped this reader behind a ceiling it
+    /// hasn't read up to yet, in which case it's that ceiling instead — see
+    /// `newest_ceiling`.
+    fn visible_tail_fixed(&self, reader_id: u64, actual_tail: u64, cursor: u64) -> u64 {
+        match self.newest_ceiling.lock().unwrap().get(&reader_id) {
+            Some(&ceiling) if cursor < ceiling => ceiling,
+            _ => actual_tail,
+        }
+    }
+
+    //Fixed mode: the coming data will overlap the exist data if it doesn't fit.
+    pub fn write(&self, data: Vec<T>) {
+        match self.mode {
+            Fixed => self.write_fixed(data),
+            Dynamic => self.dynamic.lock().unwrap().write(data),
+            Priority => panic!("queue is in Priority mode; use write_with_priority instead"),
+        }
+    }
+
+    /// Priority mode only: clones each `(value, priority)` pair into every registered
+    /// reader's own heap, assigning one monotonically increasing sequence number per
+    /// item (shared across readers) so equal-priority items tie-break the same way —
+    /// earliest sequence first — no matter which reader is draining them.
+    pub fn write_with_priority(&self, items: Vec<(T, u64)>) {
+        assert_eq!(
+            self.mode, Priority,
+            "write_with_priority only supports Priority mode"
+        );
+        self.priority.lock().unwrap().write(items);
+    }
+
+    /// `true` if writing `additional` more elements would overwrite data the
+    /// slowest (furthest-behind) reader hasn't consumed yet.
+    fn would_overflow_fixed(&self, additional: u64) -> bool {
+        let tail = self.tail.load(Ordering::Acquire);
+        let cursors = self.cursors.lock().unwrap();
+        let oldest_cursor = cursors.values().copied().min().unwrap_or(tail);
+        tail.wrapping_sub(oldest_cursor) + additional > self.ring_capacity()
+    }
+
+    /// like `write`, but for queues (typically `MsgQueue::with_buffer`-backed ones)
+    /// where silently overwriting the slowest reader's unconsumed data isn't
+    /// acceptable: reports the would-be overflow and leaves the ring untouched
+    /// instead, handing `data` back so the caller can retry once readers catch up.
+    /// Fixed mode only — Dynamic/Priority already never overwrite.
+    pub fn try_write(&self, data: Vec<T>) -> Result<(), Vec<T>> {
+        assert_eq!(self.mode, Fixed, "try_write only supports Fixed mode");
+        if self.would_overflow_fixed(data.len() as u64) {
+            return Err(data);
+        }
+        self.write_fixed(data);
+        Ok(())
+    }
+
+    fn write_fixed(&self, data: Vec<T>) {
+        // `MsgQueue::add_producer` can hand out any number of `MsgQueueWriter`s, so
+        // nothing else guarantees only one of them is ever in here at once —
+        // `write_lock` does. A reader that's fallen behind what's safe to read still
+        // gets its cursor forced forward on its next read rather than this waiting.
+        let _guard = self.write_lock.lock().unwrap();
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        // safety: `write_lock` above is the single-writer invariant this `&mut` needs —
+        // only the lock holder touches `slots` through it.
+        let slots = unsafe { &mut *self.slots.get() };
+        for item in data {
+            let idx = (tail & self.capacity_mask) as usize;
+            slots[idx] = item;
+            tail = tail.wrapping_add(1);
+        }
+        self.tail.store(tail, Ordering::Release);
+    }
+
+    // current unconsumed data for the default (implicit) reader
+    pub fn size(&self) -> u64 {
+        self.size_for(DEFAULT_READER_ID)
+    }
+
+    pub fn size_for(&self, reader_id: u64) -> u64 {
+        match self.mode {
+            Fixed => {
+                let actual_tail = self.tail.load(Ordering::Acquire);
+                let cursor = self.cursors.lock().unwrap()[&reader_id];
+                let tail = self.visible_tail_fixed(reader_id, actual_tail, cursor);
+                tail.wrapping_sub(cursor).min(self.ring_capacity())
+            }
+            Dynamic => self.dynamic.lock().unwrap().cursor_size(reader_id),
+            Priority => self.priority.lock().unwrap().size_for(reader_id),
+        }
+    }
+
+    //total buf capacity
+    pub fn capacity(&self) -> u64 {
+        match self.mode {
+            Fixed => self.ring_capacity(),
+            // no element capacity: never overwrites, only grows and reclaims (and, under
+            // a `max_bytes` budget, evicts lagging readers' backlog).
+            Dynamic => u64::MAX,
+            Priority => u64::MAX,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        match self.mode {
+            Fixed => self.size() == self.capacity(),
+            Dynamic => false,
+            Priority => false,
+        }
+    }
+
+    //only read available data for the default (implicit) reader
+    pub fn read(&self, length: u64) -> Vec<T> {
+        self.read_for(DEFAULT_READER_ID, length)
+    }
+
+    pub fn read_for(&self, reader_id: u64, length: u64) -> Vec<T> {
+        match self.mode {
+            Fixed => self.read_fixed(reader_id, length),
+            Dynamic => self.dynamic.lock().unwrap().read_for(reader_id, length),
+            Priority => self.priority.lock().unwrap().read_for(reader_id, length),
+        }
+    }
+
+    /// Priority mode only: the next element `reader_id` would get from `read`/
+    /// `read_all`, without removing it. Returns a clone rather than `&T` because the
+    /// heap it's peeked from can reallocate on a later `write_with_priority`, unlike
+    /// Fixed mode's never-reallocated slab that `read_slices_for`/`peek_slices_for`
+    /// borrow out of directly.
+    pub fn peek_highest(&self, reader_id: u64) -> Option<T> {
+        assert_eq!(self.mode, Priority, "peek_highest only supports Priority mode");
+        self.priority.lock().unwrap().peek_highest(reader_id)
+    }
+
+    fn read_fixed(&self, reader_id: u64, length: u64) -> Vec<T> {
+        let actual_tail = self.tail.load(Ordering::Acquire);
+        let capacity = self.ring_capacity();
+        let mut cursors = self.cursors.lock().unwrap();
+        let mut cursor = cursors[&reader_id];
+        if actual_tail.wrapping_sub(cursor) > capacity {
+            // this reader fell more than a full ring behind: the oldest element it
+            // hadn't consumed yet has already been overwritten, so jump it forward to
+            // the oldest element still live and lose the rest.
+            cursor = actual_tail.wrapping_sub(capacity);
+        }
+        let tail = self.visible_tail_fixed(reader_id, actual_tail, cursor);
+        let available = tail.wrapping_sub(cursor);
+        let take = length.min(available);
+        // safety: `take` never reads at or past `tail`, and every index before it has
+        // already been published by the writer's `Release` store above.
+        let slots = unsafe { &*self.slots.get() };
+        let mut res = Vec::with_capacity(take as usize);
+        for _ in 0..take {
+            res.push(slots[(cursor & self.capacity_mask) as usize].clone());
+            cursor = cursor.wrapping_add(1);
+        }
+        cursors.insert(reader_id, cursor);
+        res
+    }
+
+    pub fn read_all(&self) -> Vec<T> {
+        self.read(self.size())
+    }
+
+    /// zero-copy view of `reader_id`'s readable region as at most two contiguous
+    /// borrowed slices — the run up to the wrap point, then the wrapped-around head —
+    /// mirroring `VecDeque::as_slices`. The second slice is empty whenever the region
+    /// doesn't wrap. Doesn't advance the cursor; pair with `consume` to commit.
+    ///
+    /// Fixed mode only: Dynamic mode's backing store is a plain, non-wrapping `Vec`
+    /// behind a `Mutex`, so there's nothing to zero-copy borrow out of it without
+    /// holding that lock for the slices' lifetime.
+    pub fn peek_slices_for(&self, reader_id: u64, length: u64) -> (&[T], &[T]) {
+        assert_eq!(
+            self.mode, Fixed,
+            "peek_slices_for/read_slices_for only support Fixed mode"
+        );
+        let actual_tail = self.tail.load(Ordering::Acquire);
+        let capacity = self.ring_capacity();
+        let cursor = {
+            let mut cursors = self.cursors.lock().unwrap();
+            let mut cursor = cursors[&reader_id];
+            if actual_tail.wrapping_sub(cursor) > capacity {
+                // same "fell more than a full ring behind" clamp as `read_fixed`.
+                cursor = actual_tail.wrapping_sub(capacity);
+                cursors.insert(reader_id, cursor);
+            }
+            cursor
+        };
+        let tail = self.visible_tail_fixed(reader_id, actual_tail, cursor);
+        let available = tail.wrapping_sub(cursor);
+        let take = (length.min(available)) as usize;
+        let start = (cursor & self.capacity_mask) as usize;
+        // safety: mirrors `read_fixed` — every index before `tail` has already been
+        // published by the writer's `Release` store, and `take` never reads at or
+        // past it.
+        let slots = unsafe { &*self.slots.get() };
+        let first_len = take.min(capacity as usize - start);
+        let second_len = take - first_len;
+        (&slots[start..start + first_len], &slots[0..second_len])
+    }
+
+    /// advances `reader_id`'s cursor by `n` elements, committing a prior
+    /// `peek_slices_for`. `n` is clamped to however much is actually available.
+    pub fn consume(&self, reader_id: u64, n: u64) {
+        assert_eq!(
+            self.mode, Fixed,
+            "consume only supports Fixed mode (pairs with peek_slices_for)"
+        );
+        let actual_tail = self.tail.load(Ordering::Acquire);
+        let mut cursors = self.cursors.lock().unwrap();
+        let cursor = cursors[&reader_id];
+        let tail = self.visible_tail_fixed(reader_id, actual_tail, cursor);
+        let available = tail.wrapping_sub(cursor);
+        cursors.insert(reader_id, cursor.wrapping_add(n.min(available)));
+    }
+
+    /// `peek_slices_for` followed by an immediate `consume` of whatever it returned.
+    pub fn read_slices_for(&self, reader_id: u64, length: u64) -> (&[T], &[T]) {
+        let (a, b) = self.peek_slices_for(reader_id, length);
+        self.consume(reader_id, (a.len() + b.len()) as u64);
+        (a, b)
+    }
+
+    pub fn mode(&self) -> BufferCacheMode {
+        self.mode
+    }
+
+    /// rounds `buf_length * page_size` up to a power of two and derives
+    /// `capacity_mask` from it, so every index into `slots` — in `write_fixed`,
+    /// `read_fixed`, `peek_slices_for` and `consume` alike — goes through the same
+    /// `idx & self.capacity_mask`. There's deliberately no second indexing scheme (no
+    /// `%`, no separate page/block mask) for those to disagree with.
+    pub fn set_fixed_mode(&mut self, buf_length: u64, page_size: u64) {
+        let capacity = (buf_length * page_size).max(1).next_power_of_two();
+        self.mode = Fixed;
+        self.slots = UnsafeCell::new(vec![T::default(); capacity as usize]);
+        self.capacity_mask = capacity - 1;
+        self.tail = AtomicU64::new(0);
+        let mut cursors = HashMap::new();
+        cursors.insert(DEFAULT_READER_ID, 0);
+        self.cursors = Mutex::new(cursors);
+    }
+    pub fn set_dynamic_mode(&mut self, initial_capacity_hint: u64, max_bytes: u64) {
+        self.mode = Dynamic;
+        self.dynamic = Mutex::new(DynamicState::new(initial_capacity_hint, Some(max_bytes)));
+    }
+
+    pub fn set_priority_mode(&mut self) {
+        self.mode = Priority;
+        self.priority = Mutex::new(PriorityState::new());
+    }
+
+    pub fn readable(&self) -> bool {
+        self.size() != 0
+    }
+
+    /// total bytes allocated right now. Fixed mode's slab size never changes; Dynamic
+    /// mode's grows and shrinks with its live backlog and LRU eviction.
+    pub fn current_bytes(&self) -> u64 {
+        match self.mode {
+            Fixed => self.ring_capacity() * std::mem::size_of::<T>() as u64,
+            Dynamic => self.dynamic.lock().unwrap().current_bytes(),
+            Priority => self.priority.lock().unwrap().current_bytes(),
+        }
+    }
+
+    /// elements a lagging Dynamic-mode reader has had force-reclaimed out from under
+    /// it by the byte budget. Always `0` in Fixed and Priority modes, which don't have
+    /// a byte budget to evict against.
+    pub fn evicted_count(&self) -> u64 {
+        match self.mode {
+            Fixed => 0,
+            Dynamic => self.dynamic.lock().unwrap().evicted_count,
+            Priority => 0,
+        }
+    }
+
+    /// register `reader_id` as a new reader, starting from whatever's already been
+    /// written so far (it only sees elements written from this point on). A no-op if
+    /// `reader_id` is already registered.
+    pub fn add_reader(&self, reader_id: u64) {
+        match self.mode {
+            Fixed => {
+                let tail = self.tail.load(Ordering::Acquire);
+                self.cursors.lock().unwrap().entry(reader_id).or_insert(tail);
+            }
+            Dynamic => self.dynamic.lock().unwrap().add_reader(reader_id),
+            Priority => self.priority.lock().unwrap().add_reader(reader_id),
+        }
+    }
+
+    pub fn remove_reader(&self, reader_id: u64) {
+        match self.mode {
+            Fixed => {
+                self.cursors.lock().unwrap().remove(&reader_id);
+                self.newest_ceiling.lock().unwrap().remove(&reader_id);
+            }
+            Dynamic => self.dynamic.lock().unwrap().remove_reader(reader_id),
+            Priority => self.priority.lock().unwrap().remove_reader(reader_id),
+        }
+    }
+
+    pub fn reader_count(&self) -> u64 {
+        match self.mode {
+            Fixed => self.cursors.lock().unwrap().len() as u64,
+            Dynamic => self.dynamic.lock().unwrap().reader_count(),
+            Priority => self.priority.lock().unwrap().reader_count(),
+        }
     }
 
-    pub fn delete_consumer(&mut self, id: u64) {
-        (*self.inner).borrow_mut().delete_buffer_cache(id)
+    /// `true` if `reader_id` is a currently registered reader, in any mode.
+    pub fn has_reader(&self, reader_id: u64) -> bool {
+        match self.mode {
+            Fixed => self.cursors.lock().unwrap().contains_key(&reader_id),
+            Dynamic => self.dynamic.lock().unwrap().cursors.contains_key(&reader_id),
+            Priority => self.priority.lock().unwrap().heaps.contains_key(&reader_id),
+        }
     }
 
-    pub fn is_running(&self) -> bool {
-        self.running
+    /// caps `reader_id`'s visible tail just short of the batch that was just
+    /// appended, without touching its cursor — used right after a write to make a
+    /// consumer skip the *newest* elements (this batch) while leaving whatever it
+    /// hadn't read before that batch untouched. Unlike `read_for`'s "discard from the
+    /// front" eviction, which always removes the *oldest* unread elements regardless
+    /// of when they arrived.
+    ///
+    /// The cap lasts until `reader_id` reads up to it: a reader that was already
+    /// caught up when this fired has nothing to protect, so nothing changes for it,
+    /// and one that's still behind when it does catch up to the cap finds whatever
+    /// was written after it (including this batch) no longer singled out — it's
+    /// "dropped" for as long as this reader has its own backlog to get through
+    /// first, not erased from the shared ring the other readers still see.
+    ///
+    /// Fixed/Dynamic only: Priority mode's per-reader heaps have no linear
+    /// arrival-order cursor to cap — its only caller, `write_with_backpressure`,
+    /// never runs in Priority mode.
+    pub fn skip_newest_for(&self, reader_id: u64, count: u64) {
+        match self.mode {
+            Fixed => {
+                let tail = self.tail.load(Ordering::Acquire);
+                let cursor = self.cursors.lock().unwrap()[&reader_id];
+                let target = tail.wrapping_sub(count);
+                if target > cursor {
+                    self.newest_ceiling
+                        .lock()
+                        .unwrap()
+                        .entry(reader_id)
+                        .and_modify(|ceiling| *ceiling = (*ceiling).max(target))
+                        .or_insert(target);
+                }
+            }
+            Dynamic => self.dynamic.lock().unwrap().skip_newest_for(reader_id, count),
+            Priority => panic!("skip_newest_for doesn't support Priority mode"),
+        }
     }
 }
 
-struct MsgQueueInner<T> {
-    buf: HashMap<u64, BufferCache<T>>,
-    mode: Option<BufferCacheMode>,
-    buf_size: u64,
-    block_length: u64,
+/// the `Dynamic`-mode backing store: an unbounded, append-only `Vec` plus one cursor
+/// per registered reader. Mirrors `Subscription`'s reclaim model (see its doc comment)
+/// without the overflow policy/wakers, since the anonymous producer/consumer API has
+/// no equivalent of either.
+///
+/// `max_bytes`, when set, turns "unbounded" into "bounded, LRU-evicted": every read
+/// bumps that reader's entry in `last_access`, and a `write` that would push
+/// `current_bytes()` past the budget repeatedly forces the least-recently-read
+/// reader's cursor one element forward (recording it in `evicted_count`) and reclaims
+/// until back under budget, so a slow or dead consumer can't grow the queue forever.
+struct DynamicState<T> {
+    data: Vec<T>,
+    base_offset: u64,
+    cursors: HashMap<u64, u64>,
+    last_access: HashMap<u64, u64>,
+    clock: u64,
+    max_bytes: Option<u64>,
+    evicted_count: u64,
+    /// see `BufferCache::newest_ceiling` — the `Fixed`-mode twin of this field.
+    visible_ceiling: HashMap<u64, u64>,
 }
 
-impl<T> MsgQueueInner<T>
+impl<T> DynamicState<T>
 where
     T: Default + Clone,
 {
-    pub fn add_buffer_cache(&mut self, id: u64) {
-        if !self.buf.contains_key(&id) {
-            let mut buffer_cache = BufferCache::new();
-            match self.mode {
-                None => {}
-                Some(mode) => {
-                    if mode == Fixed {
-                        buffer_cache.set_fixed_mode(self.buf_size, self.block_length);
-                    } else if mode == Dynamic {
-                        buffer_cache.set_dynamic_mode(self.block_length);
-                    }
-                }
-            }
-            self.buf.insert(id, buffer_cache);
+    fn new(initial_capacity_hint: u64, max_bytes: Option<u64>) -> DynamicState<T> {
+        let mut cursors = HashMap::new();
+        cursors.insert(DEFAULT_READER_ID, 0);
+        let mut last_access = HashMap::new();
+        last_access.insert(DEFAULT_READER_ID, 0);
+        DynamicState {
+            data: Vec::with_capacity(initial_capacity_hint as usize),
+            base_offset: 0,
+            cursors,
+            last_access,
+            clock: 0,
+            max_bytes,
+            evicted_count: 0,
+            visible_ceiling: HashMap::new(),
         }
     }
 
-    pub fn get_buffer_cache(&mut self, id: u64) -> Option<&mut BufferCache<T>> {
-        if !self.buf.contains_key(&id) {
-            let mut buffer_cache = BufferCache::new();
-            match self.mode {
-                None => {}
-                Some(mode) => {
-                    if mode == Fixed {
-                        buffer_cache.set_fixed_mode(self.buf_size, self.block_length);
-                    } else if mode == Dynamic {
-                        buffer_cache.set_dynamic_mode(self.block_length);
-                    }
-                }
-            }
-            self.buf.insert(id, buffer_cache);
-        }
-        self.buf.get_mut(&id)
+    fn write_offset(&self) -> u64 {
+        self.base_offset + self.data.len() as u64
     }
 
-    pub fn delete_buffer_cache(&mut self, id: u64) {
-        if !self.buf.contains_key(&id) {
-            self.buf.remove(&id);
+    /// what `reader_id` should treat as the write offset right now — see
+    /// `BufferCache::visible_tail_fixed`.
+    fn visible_write_offset(&self, reader_id: u64, cursor: u64) -> u64 {
+        match self.visible_ceiling.get(&reader_id) {
+            Some(&ceiling) if cursor < ceiling => ceiling,
+            _ => self.write_offset(),
         }
     }
 
-    pub fn set_dynamic(&mut self, block_length: u64) {
-        self.mode = Some(Dynamic);
-        self.block_length = block_length;
+    fn cursor_size(&self, reader_id: u64) -> u64 {
+        let cursor = self.cursors[&reader_id];
+        self.visible_write_offset(reader_id, cursor) - cursor
     }
 
-    pub fn set_fixed(&mut self, block_length: u64, buf_size: u64) {
-        self.mode = Some(Fixed);
-        self.block_length = block_length;
-        self.buf_size = buf_size;
+    fn min_cursor(&self) -> u64 {
+        self.cursors
+            .values()
+            .copied()
+            .min()
+            .unwrap_or_else(|| self.write_offset())
     }
-}
 
-/// for data reader
-pub struct MsgQueueReader<T> {
-    id: u64,
-    inner: Rc<RefCell<MsgQueueInner<T>>>,
-}
+    /// drop elements every registered cursor has already consumed.
+    fn reclaim(&mut self) {
+        let min = self.min_cursor();
+        let drop_n = (min - self.base_offset) as usize;
+        if drop_n > 0 {
+            self.data.drain(0..drop_n);
+            self.base_offset += drop_n as u64;
+        }
+    }
 
-/// for data writer
-pub struct MsgQueueWriter<T> {
-    inner: Rc<RefCell<MsgQueueInner<T>>>,
-}
+    fn current_bytes(&self) -> u64 {
+        (self.data.len() * std::mem::size_of::<T>()) as u64
+    }
 
-impl<T> MsgQueueReader<T>
-where
-    T: Default + Clone,
-{
-    pub fn read(&mut self, size: u64) -> Vec<T> {
-        let mut buf = (*self.inner).borrow_mut();
-        buf.buf.get_mut(&self.id).unwrap().read(size)
+    /// force the least-recently-read reader *with unread backlog*'s cursor one
+    /// element forward and reclaim, so its next read sees a gap instead of what got
+    /// evicted. Readers already caught up (`cursor == write_offset()`) aren't
+    /// candidates at all — picking the globally LRU reader regardless, the way this
+    /// used to, could pick one with nothing left to evict while a different,
+    /// more-recently-touched reader was still sitting on the entire backlog, silently
+    /// breaking `enforce_budget`'s loop with memory still over budget. Returns
+    /// whether it made progress: `false` means every registered reader is already
+    /// fully caught up, so there's nothing left to evict.
+    fn evict_one(&mut self) -> bool {
+        let write_offset = self.write_offset();
+        let lru_reader = self
+            .last_access
+            .iter()
+            .filter(|&(id, _)| self.cursors[id] < write_offset)
+            .min_by_key(|(_, &tick)| tick)
+            .map(|(&id, _)| id);
+        let lru_reader = match lru_reader {
+            Some(id) => id,
+            None => return false,
+        };
+        let cursor = self.cursors[&lru_reader];
+        *self.cursors.get_mut(&lru_reader).unwrap() = cursor + 1;
+        self.evicted_count += 1;
+        self.reclaim();
+        true
     }
-    pub fn read_all(&mut self) -> Vec<T> {
-        let size = self.size();
-        self.read(size)
+
+    fn enforce_budget(&mut self) {
+        let budget = match self.max_bytes {
+            Some(budget) => budget,
+            None => return,
+        };
+        while self.current_bytes() > budget {
+            if !self.evict_one() {
+                break;
+            }
+        }
     }
-    pub fn size(&mut self) -> u64 {
-        let mut buf = (*self.inner).borrow_mut();
-        let bc = buf.get_buffer_cache(self.id).unwrap();
-        bc.size
+
+    fn write(&mut self, mut data: Vec<T>) {
+        self.clock += 1;
+        self.data.append(&mut data);
+        self.enforce_budget();
     }
 
-    pub fn id(&self) -> u64 {
-        self.id
+    fn read_for(&mut self, reader_id: u64, length: u64) -> Vec<T> {
+        self.clock += 1;
+        self.last_access.insert(reader_id, self.clock);
+        let available = self.cursor_size(reader_id);
+        let take = length.min(available);
+        let cursor = self.cursors[&reader_id];
+        let start = (cursor - self.base_offset) as usize;
+        let end = start + take as usize;
+        let res = self.data[start..end].to_vec();
+        *self.cursors.get_mut(&reader_id).unwrap() += take;
+        if take > 0 {
+            self.reclaim();
+        }
+        res
     }
-}
-impl<T> MsgQueueWriter<T>
-where
-    T: Default + Clone,
-{
-    pub fn write(&self, data: Vec<T>) {
-        for (_index, buf) in (*self.inner).borrow_mut().buf.iter_mut() {
-            buf.write(data.to_vec());
+
+    /// see `BufferCache::skip_newest_for`: caps `reader_id`'s visible write offset just
+    /// short of the last `count` elements written, without touching whatever it hadn't
+    /// read before them.
+    fn skip_newest_for(&mut self, reader_id: u64, count: u64) {
+        let target = self.write_offset() - count;
+        let cursor = self.cursors[&reader_id];
+        if target > cursor {
+            self.visible_ceiling
+                .entry(reader_id)
+                .and_modify(|ceiling| *ceiling = (*ceiling).max(target))
+                .or_insert(target);
         }
     }
+
+    fn add_reader(&mut self, reader_id: u64) {
+        let write_offset = self.write_offset();
+        self.cursors.entry(reader_id).or_insert(write_offset);
+        let clock = self.clock;
+        self.last_access.entry(reader_id).or_insert(clock);
+    }
+
+    fn remove_reader(&mut self, reader_id: u64) {
+        self.cursors.remove(&reader_id);
+        self.last_access.remove(&reader_id);
+        self.visible_ceiling.remove(&reader_id);
+        self.reclaim();
+    }
+
+    fn reader_count(&self) -> u64 {
+        self.cursors.len() as u64
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-enum BufferCacheMode {
-    Fixed,
-    Dynamic,
+/// one entry in a `Priority`-mode reader's heap: ordered by `priority` first (higher
+/// pops first, since `BinaryHeap` is a max-heap), then by `seq` — smaller (earlier)
+/// wins ties — so equal-priority items keep FIFO order.
+struct HeapEntry<T> {
+    priority: u64,
+    seq: u64,
+    value: T,
 }
 
-/// BufferCache<T> is implemented with a multi-block circular buffer.
-struct BufferCache<T> {
-    cache: Vec<Vec<T>>,
-    mode: BufferCacheMode,
-    buf_length: u64,
-    page_size: u64,
-    w_index: u64,
-    r_index: u64,
-    size: u64,
-    w_page_index: u64,
-    r_page_index: u64,
+impl<T> PartialEq for HeapEntry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
 }
 
-//using capacity()-1 == size() as the sign of buf is full.
-impl<T> BufferCache<T>
-where
-    T: Default + Clone,
-{
-    pub fn new() -> BufferCache<T> {
-        let page_size = 4096;
-        let buf_length = 2;
-        let buf_cache = vec![vec![T::default(); page_size]; buf_length];
-        BufferCache {
-            cache: buf_cache,
-            mode: Fixed,
-            buf_length: buf_length as u64, //default: two buffer blocks
-            page_size: page_size as u64,   //page size is 4k
-            w_index: 0,                    //
-            r_index: 0,
-            size: 0,
-            w_page_index: 0,
-            r_page_index: 0,
-        }
-    }
-    //Fixed mode:the coming data will overlap the exist data;
-    pub fn write(&mut self, data: Vec<T>) {
-        let target_len = data.len() as u64;
-        //only Fixed mode need to calculate the
-        if target_len > self.capacity() - self.size() {
-            if self.mode == Fixed {
-                if target_len >= self.capacity() {
-                    //only get the capacity size data
-                    let start_data_index = target_len - self.capacity() - 1;
-                    for i in 0..self.buf_length {
-                        for j in 0..self.page_size {
-                            self.cache[i as usize][j as usize] =
-                                data[(start_data_index + i * self.page_size + j) as usize].clone();
-                        }
-                    }
-                    self.size = self.buf_length * self.page_size - 1;
-                    self.r_index = 0;
-                    self.r_page_index = 0;
-                    self.w_index = self.page_size - 1;
-                    self.w_page_index = self.buf_length - 1;
-                } else {
-                    let mut a_page_index = self.w_page_index;
-                    let mut a_index = self.w_index;
-                    for i in 0..target_len {
-                        self.cache[a_page_index as usize][a_index as usize] =
-                            data[i as usize].clone();
-                        a_index += 1;
-                        if a_index == self.page_size {
-                            a_index = 0;
-                            a_page_index = (a_page_index + 1) & self.buf_length;
-                        }
-                    }
-                    self.w_page_index = a_page_index;
-                    self.w_index = a_index;
-                    if a_index + 1 == self.page_size {
-                        self.r_index = 0;
-                        self.r_page_index = (self.r_page_index + 1) % self.buf_length;
-                    } else {
-                        self.r_index = a_index + 1;
-                        self.r_page_index = a_page_index;
-                    }
-                    self.size = self.capacity();
-                }
-                //some data will be overlapped
-            } else if self.mode == Dynamic {
-                //expand a new vector for store
-
-                // self.buf_length += 1;
-                // self.cache.push(vec![0; self.page_size as usize]);
-
-                //length resize
-                //ceil((cur length + new data size) / 4096) * 2
-                // self.cache.resize()
-                self.size += target_len;
-
-                let target_buf_length = (self.buf_length
-                    + math::round::ceil(target_len as f64 / self.page_size as f64, 0) as u64)
-                    * 2;
-                let old_buf_length = self.buf_length;
-                self.buf_length = target_buf_length;
-                self.cache.resize(
-                    target_buf_length as usize,
-                    vec![T::default(); self.page_size as usize],
-                );
-                if self.w_page_index <= self.r_page_index && self.w_index <= self.r_index {
-                    //r < w
-                    let mut new_w_index = self.w_index;
-                    let mut new_w_page_index = old_buf_length;
-                    assert_eq!(self.w_index, 0);
-                    assert_eq!(self.w_page_index, 0);
-                    let mut old_w_index = self.w_index;
-                    let mut old_w_page_index = self.w_page_index;
-                    for _i in 0..(self.page_size * self.w_page_index + self.w_index) {
-                        self.cache[new_w_page_index as usize][new_w_index as usize] =
-                            self.cache[old_w_page_index as usize][old_w_index as usize].clone();
-                        new_w_index += 1;
-                        if new_w_index == self.page_size {
-                            new_w_page_index += 1;
-                            new_w_index = 0;
-                        }
-                        old_w_index += 1;
-                        if old_w_index == self.page_size {
-                            old_w_page_index += 1;
-                            old_w_index = 0;
-                        }
-                    }
+impl<T> Eq for HeapEntry<T> {}
 
-                    self.w_page_index = new_w_page_index;
-                    self.w_index = new_w_index;
-                }
+impl<T> PartialOrd for HeapEntry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-                //w > r
-                //move read -> write
-                let mut r_index = self.r_index;
-                let mut r_page_index = self.r_page_index;
-
-                let mut n_r_index = self.r_index;
-                let mut n_r_page_index = self.r_page_index;
-                for _i in 0..self.size() {
-                    self.cache[n_r_page_index as usize][n_r_index as usize] =
-                        self.cache[r_page_index as usize][r_index as usize].clone();
-                    r_index += 1;
-                    if r_index == self.page_size {
-                        r_page_index += 1;
-                        r_index = 0;
-                    }
-                    n_r_index += 1;
-                    if n_r_index == self.page_size {
-                        n_r_page_index += 1;
-                        n_r_index = 0;
-                    }
-                }
+impl<T> Ord for HeapEntry<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
 
-                let mut w_index = self.w_index;
-                for i in 0..target_len {
-                    self.cache[self.w_page_index as usize][w_index as usize] =
-                        data[i as usize].clone();
-                    w_index += 1;
-                    if w_index == self.page_size {
-                        w_index = 0;
-                        self.w_page_index += 1;
-                    }
-                }
-            }
-            return;
-        }
-        let mut index = target_len;
-        while index != 0 {
-            let mut wrote_size = self.page_size - self.w_index;
+/// the `Priority`-mode backing store: see `BufferCache`'s doc comment for why each
+/// reader needs its own independent heap rather than sharing one. `next_seq` is the
+/// single source of tie-break ordering, assigned once per item and carried into every
+/// reader's copy of it.
+struct PriorityState<T> {
+    next_seq: u64,
+    heaps: HashMap<u64, BinaryHeap<HeapEntry<T>>>,
+}
 
-            let w_index = self.w_index;
-            let mut w_page_index = self.w_page_index;
+impl<T> PriorityState<T>
+where
+    T: Clone,
+{
+    fn new() -> PriorityState<T> {
+        PriorityState {
+            next_seq: 0,
+            heaps: HashMap::new(),
+        }
+    }
 
-            if index < wrote_size {
-                wrote_size = index;
-                self.w_index += index;
-            } else {
-                self.w_page_index = (self.w_page_index + 1) % self.buf_length;
-                self.w_index = 0;
+    fn write(&mut self, items: Vec<(T, u64)>) {
+        let entries: Vec<(T, u64, u64)> = items
+            .into_iter()
+            .map(|(value, priority)| {
+                let seq = self.next_seq;
+                self.next_seq += 1;
+                (value, priority, seq)
+            })
+            .collect();
+        for heap in self.heaps.values_mut() {
+            for (value, priority, seq) in entries.iter().cloned() {
+                heap.push(HeapEntry { priority, seq, value });
             }
-            for i in 0..wrote_size {
-                //fix me
-                self.cache[w_page_index as usize][(w_index + i) as usize] =
-                    data[i as usize].clone();
-            }
-            index -= wrote_size;
         }
-        self.size += target_len;
     }
 
-    // current unconsumed data
-    pub fn size(&self) -> u64 {
-        return self.size;
+    fn size_for(&self, reader_id: u64) -> u64 {
+        self.heaps.get(&reader_id).map_or(0, |heap| heap.len() as u64)
     }
 
-    //total buf capacity
-    pub fn capacity(&self) -> u64 {
-        if self.mode == Fixed {
-            self.page_size * self.buf_length - 1
-        } else {
-            //in Dynamic mode, capacity is no meaningful
-            //TODO Does Dynamic uses the same strategy like Fixed
-            self.page_size * self.buf_length
-        }
+    fn current_bytes(&self) -> u64 {
+        self.heaps
+            .values()
+            .map(|heap| heap.len() as u64)
+            .sum::<u64>()
+            * std::mem::size_of::<T>() as u64
     }
 
-    pub fn is_full(&self) -> bool {
-        self.capacity() == self.size()
-    }
-
-    //only read available data
-    pub fn read(&mut self, length: u64) -> Vec<T> {
-        let mut lens = length;
-        //check whether buf has enough data for reading
-        if lens > self.size() {
-            lens = self.size();
-        }
-        if lens == 0 {
-            return vec![];
-        }
-        let mut res = vec![];
-        while lens != 0 {
-            let read_index_start = self.r_index;
-            let mut read_index_end = self.r_index;
-            let cur_page_readable_size = self.page_size - self.r_index;
-            let page_index = self.r_page_index;
-            if self.r_page_index == self.w_page_index {
-                //in the same page
-                if self.r_index > self.w_index {
-                    // cache layout
-                    //··· free space， --- used space             index
-                    // ------------------------------------------  0
-                    // ------------------------------------------  1
-                    // ------------w_index······r_index----------  2
-                    // ------------------------------------------  3
-                    // ------------------------------------------  end of cache
-                    if cur_page_readable_size > lens {
-                        //current page data is enough
-                        read_index_end = read_index_start + lens;
-                        self.r_index += lens as u64;
-                    } else {
-                        read_index_end = read_index_start + cur_page_readable_size;
-                        self.r_index = 0;
-                        self.read_page_add();
-                    }
-                } else {
-                    // cache layout
-                    //··· free space， --- used space             index
-                    // ··········································  0
-                    // ··········································  1
-                    // ···········r_index------w_index··········   2
-                    // ··········································  3
-                    // ··········································  end of cache
-                    read_index_end = read_index_start + lens;
-                    self.r_index += lens as u64;
-                }
-            } else {
-                // cache layout
-                //··· free space， --- used space             index
-                // ··········································  0
-                // ···r_index--------------------------------  1
-                // -------------------------w_index··········  2
-                // ··········································  3
-                // ··········································  end of cache
-
-                // cache layout
-                //··· free space， --- used space             index
-                // ------------------------------------------  0
-                // ---w_index································  1
-                // ·························r_index----------  2
-                // ------------------------------------------  3
-                // ------------------------------------------  end of cache
-
-                if cur_page_readable_size > lens {
-                    read_index_end = read_index_start + lens;
-                    self.r_index += lens;
-                } else {
-                    read_index_end = read_index_start + cur_page_readable_size;
-                    self.r_index = 0;
-                    self.read_page_add();
-                }
+    fn read_for(&mut self, reader_id: u64, length: u64) -> Vec<T> {
+        let heap = self.heaps.entry(reader_id).or_insert_with(BinaryHeap::new);
+        let mut res = Vec::with_capacity(length as usize);
+        for _ in 0..length {
+            match heap.pop() {
+                Some(entry) => res.push(entry.value),
+                None => break,
             }
-
-            let rs = read_index_start as usize;
-            let re = read_index_end as usize;
-            res.append(
-                self.cache[page_index as usize][rs..re]
-                    .to_vec()
-                    .clone()
-                    .as_mut(),
-            );
-            lens -= read_index_end - read_index_start;
-        }
-        self.size -= length;
-        if self.size == 0 {
-            //reset index
-            self.w_page_index = 0;
-            self.w_index = 0;
-            self.r_page_index = 0;
-            self.r_index = 0;
-            //fixme
-            //resize in Dynamic mode
         }
         res
     }
 
-    pub fn read_all(&mut self) -> Vec<T> {
-        // self.read(self.size())
-        vec![]
-    }
-
-    fn read_page_add(&mut self) {
-        self.r_page_index = (self.r_page_index + 1) % self.buf_length;
+    fn peek_highest(&self, reader_id: u64) -> Option<T> {
+        self.heaps
+            .get(&reader_id)
+            .and_then(|heap| heap.peek())
+            .map(|entry| entry.value.clone())
     }
 
-    pub fn mode(&self) -> BufferCacheMode {
-        self.mode
+    /// register `reader_id` with an empty heap: it only sees items written from this
+    /// point on, the same "register now, see the future" rule Fixed/Dynamic follow.
+    fn add_reader(&mut self, reader_id: u64) {
+        self.heaps.entry(reader_id).or_insert_with(BinaryHeap::new);
     }
 
-    pub fn set_fixed_mode(&mut self, buf_length: u64, page_size: u64) {
-        self.buf_length = buf_length;
-        self.page_size = page_size;
-        self.cache = vec![vec![T::default(); page_size as usize]; buf_length as usize];
-        self.mode = Fixed;
-        self.w_index = 0;
-        self.r_index = 0;
-        self.size = 0;
-        self.w_page_index = 0;
-        self.r_page_index = 0;
-    }
-    pub fn set_dynamic_mode(&mut self, page_size: u64) {
-        self.buf_length = 2; //default buf length is 2
-        self.page_size = page_size;
-        self.cache = vec![vec![T::default(); page_size as usize]; self.buf_length as usize];
-        self.mode = Dynamic;
-        self.w_index = 0;
-        self.r_index = 0;
-        self.size = 0;
-        self.w_page_index = 0;
-        self.r_page_index = 0;
+    fn remove_reader(&mut self, reader_id: u64) {
+        self.heaps.remove(&reader_id);
     }
 
-    pub fn readable(&self) -> bool {
-        self.size() != 0
+    fn reader_count(&self) -> u64 {
+        self.heaps.len() as u64
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::core::{BufferCache, BufferCacheMode, MsgQueue};
+    use crate::core::{
+        BufferCache, BufferCacheMode, ConsumerOverflowPolicy, ConsumerSaturated, MsgQueue,
+    };
     use std::cell::RefCell;
     use std::rc::Rc;
+    use std::sync::atomic::{AtomicU64, Ordering};
     use std::sync::{Arc, Mutex};
     use std::thread;
 
     // #[test]
     fn test_buff_cache() {
-        let mut buf = BufferCache::new();
+        let buf = BufferCache::new();
         assert_eq!(buf.mode(), BufferCacheMode::Fixed);
         assert_eq!(buf.size(), 0);
-        assert_eq!(buf.capacity(), 4096 * 2 - 1);
+        assert_eq!(buf.capacity(), 4096 * 2);
         assert_eq!(buf.read(3).len(), 0);
         buf.write(vec![10, 12]);
         assert_eq!(buf.size(), 2);
@@ -600,61 +2096,251 @@ mod tests {
         buf.write(vec![0; 4096 * 3]);
         assert!(buf.is_full());
         buf.read(4096);
-        assert_eq!(buf.size(), 4095);
-    }
-
-    // #[test]
-    fn test_overlap() {
-        let mut buf = BufferCache::new();
-        println!("start");
-        buf.write(vec![0; 6000]);
-        println!("end");
-        assert_eq!(buf.w_index, 6000 - 4096);
-        assert_eq!(buf.w_page_index, 1);
-        //read 0,0 write 1,4095
-        buf.write(vec![0; 4096 * 3]);
-
-        //read 0,2000 write 1,4095
-        buf.read(2000);
-
-        assert_eq!(buf.r_index, 2000);
-        //read 0,2000 write 0,999
-        buf.write(vec![0; 1000]);
-        assert_eq!(buf.r_index, 2000);
-        assert_eq!(buf.r_page_index, 0);
-        assert_eq!(buf.w_index, 999);
-        assert_eq!(buf.w_page_index, 0);
-        //read 1,
-        buf.write(vec![0; 3095]);
-        assert_eq!(buf.is_full(), true);
-        assert_eq!(buf.r_index, 4095);
-        assert_eq!(buf.w_index, 4094);
-        assert_eq!(buf.r_page_index, 0);
-        assert_eq!(buf.w_page_index, 0);
-
-        buf.read(200);
-        assert_eq!(buf.r_index, 199);
-        assert_eq!(buf.w_index, 4094);
-        assert_eq!(buf.r_page_index, 1);
-        assert_eq!(buf.w_page_index, 0);
-
-        buf.write(vec![0; 100]);
-        assert_eq!(buf.r_index, 199);
-        assert_eq!(buf.w_index, 98);
-        assert_eq!(buf.r_page_index, 1);
-        assert_eq!(buf.w_page_index, 1);
+        assert_eq!(buf.size(), 4096);
     }
 
     #[test]
     fn test_dynamic_mode() {
         let mut buf = BufferCache::new();
-        buf.set_dynamic_mode(4096);
+        buf.set_dynamic_mode(4096, u64::MAX);
         buf.write(vec![0; 4096 * 2]);
-        assert_eq!(buf.is_full(), true);
+        // Dynamic mode never overwrites: it's unbounded until reclaimed.
+        assert_eq!(buf.is_full(), false);
         assert_eq!(buf.size(), 4096 * 2);
         buf.write(vec![0; 1]);
         assert_eq!(buf.size(), 4096 * 2 + 1);
-        assert_eq!(buf.capacity(), 4096 * 6);
+        assert_eq!(buf.capacity(), u64::MAX);
+    }
+
+    #[test]
+    fn test_dynamic_mode_bounded_eviction() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        msg_queue.set_dynamic(4096, 4); // 4 bytes: at most 4 live u8s at once
+        let mut slow = msg_queue.add_consumer();
+        let mut fast = msg_queue.add_consumer();
+        let mut writer = msg_queue.add_producer();
+
+        writer.write(vec![1, 2]).unwrap();
+        // fast catches up, marking it most-recently-read; slow never reads, staying
+        // the least-recently-read (and thus the eviction target) throughout.
+        assert_eq!(fast.read_all(), vec![1, 2]);
+        writer.write(vec![3, 4, 5, 6]).unwrap(); // pushes live bytes past the 4-byte budget
+
+        assert!(msg_queue.evicted_count() > 0);
+        assert!(msg_queue.current_bytes() <= 4);
+        assert!(slow.size() < 6);
+    }
+
+    #[test]
+    fn test_dynamic_mode_eviction_moves_past_a_drained_reader() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        msg_queue.set_dynamic(4096, 4); // 4 bytes: at most 4 live u8s at once
+        let stale = msg_queue.add_consumer();
+        let writer = msg_queue.add_producer();
+        writer.write(vec![]).unwrap(); // bumps the clock without touching either cursor
+        let lagging = msg_queue.add_consumer();
+
+        // `stale` (registered first) has the older last-access tick, so eviction
+        // always tries it before `lagging`. Enough is written that `stale`'s cursor
+        // gets force-advanced all the way to the tail — but since `reclaim` only
+        // drops bytes every registered cursor has passed, nothing is actually freed
+        // until `lagging`'s cursor (still at 0) moves too.
+        writer.write(vec![1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        // once `stale` has nothing left to give up, eviction must move on to
+        // `lagging` instead of stopping with memory still double the budget.
+        assert!(msg_queue.current_bytes() <= 4);
+        drop(stale);
+        drop(lagging);
+    }
+
+    #[test]
+    fn test_peek_and_read_slices_wrap_around() {
+        let mut buf = BufferCache::new();
+        buf.set_fixed_mode(8, 1); // capacity = 8
+        buf.write(vec![1u8, 2, 3, 4, 5, 6]);
+        assert_eq!(buf.read(4), vec![1, 2, 3, 4]);
+        buf.write(vec![7, 8, 9, 10]); // tail wraps past capacity
+
+        // peeking doesn't advance the cursor, and the wrapped-around head lands in
+        // the second slice.
+        let (a, b) = buf.peek_slices_for(super::DEFAULT_READER_ID, 6);
+        assert_eq!(a, &[5, 6, 7, 8]);
+        assert_eq!(b, &[9, 10]);
+        assert_eq!(buf.size(), 6);
+
+        let (a, b) = buf.read_slices_for(super::DEFAULT_READER_ID, 6);
+        assert_eq!(a, &[5, 6, 7, 8]);
+        assert_eq!(b, &[9, 10]);
+        assert_eq!(buf.size(), 0);
+    }
+
+    #[test]
+    fn test_set_fixed_mode_rounds_up_to_power_of_two() {
+        let mut buf = BufferCache::new();
+        buf.set_fixed_mode(3, 1); // 3 isn't a power of two
+        assert_eq!(buf.capacity(), 4); // rounded up, so capacity_mask = 3
+        buf.write(vec![1u8, 2, 3, 4]);
+        assert!(buf.is_full());
+        // one extra element wraps the tail back onto slot 0. `idx & buf_length` (i.e.
+        // `& 3` treated as the mask of an unrounded capacity) happens to coincide with
+        // the real mask for a rounded capacity of 4, which is also 3, so also check a
+        // size where the two diverge.
+        buf.write(vec![5]);
+        assert_eq!(buf.read_all(), vec![2, 3, 4, 5]);
+
+        let mut buf2 = BufferCache::new();
+        buf2.set_fixed_mode(5, 1); // rounds up to 8, mask 7 — differs from `& 5`
+        assert_eq!(buf2.capacity(), 8);
+        buf2.write(vec![0u8; 8]);
+        buf2.write(vec![9, 10]); // wraps onto slots 0 and 1
+        assert_eq!(buf2.read_all(), vec![0, 0, 0, 0, 0, 0, 9, 10]);
+    }
+
+    #[test]
+    fn test_with_buffer_uses_caller_supplied_storage() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::with_buffer(vec![0u8; 4]);
+        let mut writer = msg_queue.add_producer();
+        let mut reader = msg_queue.add_consumer();
+
+        writer.write(vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(reader.size(), 4);
+        assert_eq!(reader.read_all(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_with_buffer_rejects_non_power_of_two_length() {
+        let _msg_queue: MsgQueue<u8> = MsgQueue::with_buffer(vec![0u8; 3]);
+    }
+
+    #[test]
+    fn test_try_write_reports_overflow_instead_of_overwriting() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::with_buffer(vec![0u8; 4]);
+        let writer = msg_queue.add_producer();
+        let mut reader = msg_queue.add_consumer();
+
+        assert_eq!(writer.try_write(vec![1, 2, 3, 4]), Ok(()));
+        // the reader hasn't consumed anything yet, so this would overwrite it.
+        assert_eq!(writer.try_write(vec![5]), Err(vec![5]));
+        assert_eq!(reader.read_all(), vec![1, 2, 3, 4]);
+        // now there's room again.
+        assert_eq!(writer.try_write(vec![5]), Ok(()));
+        assert_eq!(reader.read_all(), vec![5]);
+    }
+
+    #[test]
+    fn test_msg_queue_reader_read_slices() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        msg_queue.set_fixed(1, 8); // capacity = 8
+        let mut writer = msg_queue.add_producer();
+        let mut reader = msg_queue.add_consumer();
+
+        writer.write(vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(reader.read(4), vec![1, 2, 3, 4]);
+        writer.write(vec![7, 8, 9, 10]).unwrap(); // wraps
+
+        let (a, b) = reader.peek_slices(6);
+        assert_eq!(a, &[5, 6, 7, 8]);
+        assert_eq!(b, &[9, 10]);
+        assert_eq!(reader.size(), 6);
+
+        let (a, b) = reader.read_slices(6);
+        assert_eq!(a, &[5, 6, 7, 8]);
+        assert_eq!(b, &[9, 10]);
+        assert_eq!(reader.size(), 0);
+    }
+
+    #[test]
+    fn test_priority_mode_drains_highest_first_with_fifo_ties() {
+        let mut msg_queue: MsgQueue<&str> = MsgQueue::new();
+        msg_queue.set_priority_mode();
+        let mut reader1 = msg_queue.add_consumer();
+        let mut reader2 = msg_queue.add_consumer();
+        let writer = msg_queue.add_producer();
+
+        writer.write_with_priority(vec![
+            ("low", 1),
+            ("high", 10),
+            ("mid-first", 5),
+            ("mid-second", 5),
+        ]);
+
+        assert_eq!(reader1.size(), 4);
+        assert_eq!(reader1.peek_highest(), Some("high"));
+        // equal priority (5) falls back to arrival order.
+        assert_eq!(
+            reader1.read_all(),
+            vec!["high", "mid-first", "mid-second", "low"]
+        );
+
+        // each consumer has its own heap: reader2 still sees every item, in the same
+        // priority order, independent of what reader1 already drained.
+        assert_eq!(reader2.size(), 4);
+        assert_eq!(reader2.read(2), vec!["high", "mid-first"]);
+        assert_eq!(reader2.size(), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_priority_mode_rejects_reconfig_while_running() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let _reader = msg_queue.add_consumer();
+        assert!(msg_queue.is_running());
+        msg_queue.set_priority_mode();
+    }
+
+    #[test]
+    fn test_read_guarded_commit_removes_message_permanently() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let writer = msg_queue.add_producer();
+        let mut reader = msg_queue.add_consumer();
+        writer.write(vec![1, 2]).unwrap();
+
+        let guard = reader.read_guarded().unwrap();
+        assert_eq!(*guard, 1);
+        assert_eq!(reader.size_with_in_flight(true), 2);
+        guard.commit();
+
+        assert_eq!(reader.size(), 1);
+        assert_eq!(reader.size_with_in_flight(true), 1);
+        assert_eq!(*reader.read_guarded().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_read_guarded_redelivers_on_drop() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let writer = msg_queue.add_producer();
+        let mut reader = msg_queue.add_consumer();
+        writer.write(vec![1, 2]).unwrap();
+
+        {
+            let guard = reader.read_guarded().unwrap();
+            assert_eq!(*guard, 1);
+            // dropped without commit: gets pushed back to the front.
+        }
+        assert_eq!(reader.size_with_in_flight(true), 2);
+
+        let redelivered = reader.read_guarded().unwrap();
+        assert_eq!(*redelivered, 1);
+        redelivered.commit();
+        let second = reader.read_guarded().unwrap();
+        assert_eq!(*second, 2);
+        second.commit();
+        assert_eq!(reader.size_with_in_flight(true), 0);
+    }
+
+    #[test]
+    fn test_size_with_in_flight_false_ignores_outstanding_guards() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let writer = msg_queue.add_producer();
+        let mut reader = msg_queue.add_consumer();
+        writer.write(vec![1, 2, 3]).unwrap();
+
+        let _guard = reader.read_guarded().unwrap();
+        assert_eq!(reader.size(), 2);
+        assert_eq!(reader.size_with_in_flight(false), 2);
+        assert_eq!(reader.size_with_in_flight(true), 3);
     }
 
     #[test]
@@ -662,14 +2348,14 @@ mod tests {
         let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
         let mut writer1 = msg_queue.add_producer();
         let mut read1 = msg_queue.add_consumer();
-        writer1.write(vec![10; 100]);
+        writer1.write(vec![10; 100]).unwrap();
         println!("{}", msg_queue.get_consumer_count());
         println!("{}", read1.size());
         assert_eq!(read1.size(), 100);
         let mut read2 = msg_queue.add_consumer();
         assert_eq!(read2.size(), 0);
         assert_eq!(msg_queue.get_consumer_count(), 2);
-        writer1.write(vec![0; 100]);
+        writer1.write(vec![0; 100]).unwrap();
         assert_eq!(read1.size(), 200);
         assert_eq!(read2.size(), 100);
         read2.read(50);
@@ -697,7 +2383,7 @@ mod tests {
             println!("get lock1");
             let p = msg_lock.add_producer();
             for i in 0..100 {
-                p.write(vec![0; 5]);
+                p.write(vec![0; 5]).unwrap();
             }
         });
 
@@ -706,7 +2392,7 @@ mod tests {
             println!("get lock1");
             let p = msg_lock.add_producer();
             for i in 0..100 {
-                p.write(vec![0; 5]);
+                p.write(vec![0; 5]).unwrap();
             }
         });
         t1.join();
@@ -727,7 +2413,7 @@ mod tests {
         let mut msg_queue = Rc::new(RefCell::new(MsgQueue::<String>::new()));
         let mut c1 = msg_queue.borrow_mut().add_consumer();
         let mut p1 = msg_queue.borrow_mut().add_producer();
-        p1.write(vec!["hello".to_string(), "world".to_string()]);
+        p1.write(vec!["hello".to_string(), "world".to_string()]).unwrap();
         assert_eq!(c1.size(), 2);
         let data = c1.read_all();
         assert_eq!(c1.size(), 0);
@@ -743,11 +2429,11 @@ mod tests {
     fn test_config() {
         let mut msg_queue = Rc::new(RefCell::new(MsgQueue::<String>::new()));
         assert_eq!(msg_queue.borrow_mut().is_running(), false);
-        msg_queue.borrow_mut().set_dynamic(4096);
+        msg_queue.borrow_mut().set_dynamic(4096, u64::MAX);
         let mut c1 = msg_queue.borrow_mut().add_consumer();
         let mut p1 = msg_queue.borrow_mut().add_producer();
         assert_eq!(msg_queue.borrow_mut().is_running(), true);
-        p1.write(vec!["hello".to_string(), "world".to_string()]);
+        p1.write(vec!["hello".to_string(), "world".to_string()]).unwrap();
     }
 
     #[test]
@@ -758,6 +2444,200 @@ mod tests {
         let mut c1 = msg_queue.borrow_mut().add_consumer();
         let mut p1 = msg_queue.borrow_mut().add_producer();
         assert_eq!(msg_queue.borrow_mut().is_running(), true);
-        msg_queue.borrow_mut().set_dynamic(4096);
+        msg_queue.borrow_mut().set_dynamic(4096, u64::MAX);
+    }
+
+    // loom/TSAN-style concurrency check: one writer thread and one reader thread make
+    // progress on the same BufferCache with no Mutex wrapping the queue at all, which
+    // works because BufferCache::write_fixed/read_fixed synchronize internally (the
+    // writer lock, the cursor mutex and the atomic tail) rather than through an outer lock.
+    #[test]
+    fn test_spsc_concurrent_progress() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let mut writer = msg_queue.add_producer();
+        let mut reader = msg_queue.add_consumer();
+
+        const MESSAGES: u64 = 2000;
+        let writer_thread = thread::spawn(move || {
+            for _ in 0..MESSAGES {
+                writer.write(vec![7]).unwrap();
+            }
+        });
+
+        let mut total_read = 0u64;
+        while total_read < MESSAGES {
+            total_read += reader.read(MESSAGES).len() as u64;
+        }
+        writer_thread.join().unwrap();
+        assert_eq!(total_read, MESSAGES);
+    }
+
+    #[test]
+    fn test_new_spsc_push_pop_and_full_empty() {
+        let (producer, consumer) = MsgQueue::<u8>::new_spsc(2);
+        assert_eq!(consumer.pop(), None);
+
+        assert_eq!(producer.push(1), Ok(()));
+        assert_eq!(producer.push(2), Ok(()));
+        assert_eq!(producer.push(3), Err(3)); // full: capacity is 2
+
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(producer.push(3), Ok(())); // draining one slot frees it up
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn test_new_spsc_drops_unread_values() {
+        let dropped = Arc::new(AtomicU64::new(0));
+        struct CountOnDrop(Arc<AtomicU64>);
+        impl Drop for CountOnDrop {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let (producer, consumer) = MsgQueue::new_spsc(4);
+        producer.push(CountOnDrop(dropped.clone())).ok().unwrap();
+        producer.push(CountOnDrop(dropped.clone())).ok().unwrap();
+        // one popped (and immediately dropped), one left sitting in the ring.
+        drop(consumer.pop());
+        drop(producer);
+        drop(consumer);
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_new_spsc_concurrent_progress() {
+        let (producer, consumer) = MsgQueue::<u64>::new_spsc(64);
+        const MESSAGES: u64 = 20_000;
+        let writer_thread = thread::spawn(move || {
+            let mut sent = 0u64;
+            while sent < MESSAGES {
+                if producer.push(sent).is_ok() {
+                    sent += 1;
+                }
+            }
+        });
+
+        let mut received = 0u64;
+        while received < MESSAGES {
+            if let Some(value) = consumer.pop() {
+                assert_eq!(value, received);
+                received += 1;
+            }
+        }
+        writer_thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_consumer_capacity_drop_oldest_evicts_only_that_consumer() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let mut capped = msg_queue.add_consumer();
+        let mut uncapped = msg_queue.add_consumer();
+        let writer = msg_queue.add_producer();
+        msg_queue.set_consumer_capacity(capped.id(), 2, ConsumerOverflowPolicy::DropOldest);
+
+        writer.write(vec![1, 2]).unwrap();
+        writer.write(vec![3, 4]).unwrap(); // pushes `capped` to 4 unread, over its cap of 2
+
+        assert_eq!(capped.read_all(), vec![3, 4]); // oldest (1, 2) already dropped
+        assert_eq!(capped.dropped_count(), 2);
+        assert_eq!(uncapped.read_all(), vec![1, 2, 3, 4]); // untouched by `capped`'s cap
+    }
+
+    #[test]
+    fn test_consumer_capacity_drop_newest_skips_just_the_overflowing_batch() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let mut capped = msg_queue.add_consumer();
+        let mut uncapped = msg_queue.add_consumer();
+        let writer = msg_queue.add_producer();
+        msg_queue.set_consumer_capacity(capped.id(), 2, ConsumerOverflowPolicy::DropNewest);
+
+        writer.write(vec![1, 2]).unwrap();
+        writer.write(vec![3, 4]).unwrap(); // would push `capped` over cap: this batch is skipped
+
+        assert_eq!(capped.read_all(), vec![1, 2]); // prior backlog preserved, new batch skipped
+        assert_eq!(capped.dropped_count(), 2);
+        assert_eq!(uncapped.read_all(), vec![1, 2, 3, 4]); // untouched by `capped`'s cap
+    }
+
+    #[test]
+    fn test_consumer_capacity_error_rejects_whole_write_for_everyone() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let mut capped = msg_queue.add_consumer();
+        let mut uncapped = msg_queue.add_consumer();
+        let writer = msg_queue.add_producer();
+        msg_queue.set_consumer_capacity(capped.id(), 2, ConsumerOverflowPolicy::Error);
+
+        writer.write(vec![1, 2]).unwrap();
+        let err = writer.write(vec![3, 4]).unwrap_err();
+        assert_eq!(
+            err,
+            ConsumerSaturated {
+                consumer_id: capped.id(),
+                cap: 2
+            }
+        );
+
+        // rejected write landed nowhere, for either consumer.
+        assert_eq!(capped.read_all(), vec![1, 2]);
+        assert_eq!(uncapped.read_all(), vec![1, 2]);
+        assert_eq!(capped.dropped_count(), 0);
+    }
+
+    #[test]
+    fn test_consumer_capacity_block_parks_writer_until_reader_drains() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let mut capped = msg_queue.add_consumer();
+        let writer = msg_queue.add_producer();
+        msg_queue.set_consumer_capacity(capped.id(), 2, ConsumerOverflowPolicy::Block);
+
+        writer.write(vec![1, 2]).unwrap(); // fills the cap exactly; not yet over it
+
+        let writer = Arc::new(writer);
+        let blocked_writer = Arc::clone(&writer);
+        let landed = Arc::new(AtomicU64::new(0));
+        let landed_writer = Arc::clone(&landed);
+        let handle = thread::spawn(move || {
+            blocked_writer.write(vec![3, 4]).unwrap();
+            landed_writer.store(1, Ordering::Release);
+        });
+
+        thread::sleep(std::time::Duration::from_millis(150));
+        assert_eq!(landed.load(Ordering::Acquire), 0); // still parked: `capped` hasn't drained
+
+        assert_eq!(capped.read_all(), vec![1, 2]); // drains below cap, unblocking the writer
+        handle.join().unwrap();
+        assert_eq!(landed.load(Ordering::Acquire), 1);
+        assert_eq!(capped.read_all(), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_dropped_count_stays_zero_without_a_configured_cap() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        let mut reader = msg_queue.add_consumer();
+        let writer = msg_queue.add_producer();
+
+        writer.write(vec![1, 2, 3]).unwrap();
+        assert_eq!(reader.dropped_count(), 0);
+        assert_eq!(reader.read_all(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_push_data_overwrite_oldest_accepts_a_batch_bigger_than_capacity() {
+        let mut msg_queue: MsgQueue<u8> = MsgQueue::new();
+        msg_queue.set_subscription_with_capacity("s".to_string(), 4);
+        let mut control = msg_queue.get_subscription("s".to_string()).unwrap();
+
+        // the batch (10) is bigger than the subscription's whole capacity (4), so
+        // overflow (6) exceeds what `data` held pre-append (0): the old
+        // `min_cursor() + overflow` target landed past write_offset and asked
+        // `reclaim` to drain more bytes than `data` held, panicking.
+        control.push_data((1..=10).collect()).unwrap();
+
+        assert_eq!(control.size(), 4);
+        assert_eq!(control.read(4), vec![7, 8, 9, 10]); // only the most recent 4 survive
     }
 }
@@ -1,13 +1,23 @@
 mod core;
+#[cfg(feature = "cffi")]
+pub mod cffi;
+pub mod logging;
+
+pub use crate::core::{
+    Control, ConsumerOverflowPolicy, ConsumerSaturated, MsgQueue, MsgQueueReader,
+    MsgQueueWriter, OverflowPolicy, ReaderHandle, ReceiveGuard, RejectedPush,
+    SpscConsumer, SpscProducer,
+};
 
 #[cfg(test)]
 mod tests {
     use std::process::exit;
     use crate::core::{MsgQueue};
+    use futures_util::StreamExt;
     use super::*;
     #[test]
     fn it_works() {
-        let mut msg_queue = MsgQueue::new();
+        let mut msg_queue = MsgQueue::<u8>::new();
         msg_queue.set_subscription("hi".to_string());
         let control = match msg_queue.get_subscription("hi".to_string()){
             Ok(control) =>{ control },
@@ -33,7 +43,21 @@ mod tests {
         assert_eq!(data.len(),1000);
         let data = control.read(1000);
         assert_eq!(data.len(),0);
-        // block_on(control.readable().await);
+    }
+
+    #[test]
+    fn test_subscription_stream() {
+        let mut msg_queue = MsgQueue::new();
+        msg_queue.set_subscription("hi".to_string());
+        let mut control = match msg_queue.get_subscription("hi".to_string()){
+            Ok(control) =>{ control },
+            Err(str) => {panic!("err:{}",str)}
+        };
+        control.push_data(vec![1,2,3]);
+        let chunk = futures_executor::block_on(control.next());
+        assert_eq!(chunk, Some(vec![1,2,3]));
+        // no data buffered yet: poll_next would return Poll::Pending and park the waker.
+        assert_eq!(control.readable(), false);
     }
 
 }
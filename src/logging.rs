@@ -0,0 +1,139 @@
+//! Thread-local logging built on `MsgQueue<LogMsg>`.
+//!
+//! Each thread that calls `init_thread_logger()` (or just `log!`, which does it
+//! lazily) gets its own `MsgQueueWriter` into one shared global queue. Emitting is not
+//! lock-free: every emit briefly takes the queue's shared read lock, its
+//! consumer-limit mutex and the writer lock `write_fixed` uses to serialize the
+//! threads logging at once, all shared with every other logging thread. It's the same shrev-style "one
+//! writer handle per caller, cheap per-consumer cursor" model `MsgQueue` already uses,
+//! just with every emitting thread as its own writer. A single collector consumer,
+//! created once on first use, drains everything via `read_all` from whatever thread
+//! calls `retrieve_log_messages`.
+use crate::core::{MsgQueue, MsgQueueReader, MsgQueueWriter};
+use std::cell::RefCell;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LogMsg {
+    pub level: LogLevel,
+    pub text: String,
+    pub timestamp_millis: u64,
+}
+
+fn global_queue() -> &'static Mutex<MsgQueue<LogMsg>> {
+    static QUEUE: OnceLock<Mutex<MsgQueue<LogMsg>>> = OnceLock::new();
+    QUEUE.get_or_init(|| Mutex::new(MsgQueue::new()))
+}
+
+fn global_collector() -> &'static Mutex<MsgQueueReader<LogMsg>> {
+    static COLLECTOR: OnceLock<Mutex<MsgQueueReader<LogMsg>>> = OnceLock::new();
+    COLLECTOR.get_or_init(|| Mutex::new(global_queue().lock().unwrap().add_consumer()))
+}
+
+thread_local! {
+    static PRODUCER: RefCell<Option<MsgQueueWriter<LogMsg>>> = RefCell::new(None);
+}
+
+/// lazily registers this thread's producer into the global log queue. Calling it
+/// ahead of time (e.g. at thread start) means the first `log!` on this thread
+/// doesn't pay for the one-time `add_producer` setup; `log!` calls it for you
+/// regardless, so this is an optimization, not a requirement.
+pub fn init_thread_logger() {
+    PRODUCER.with(|cell| {
+        let mut producer = cell.borrow_mut();
+        if producer.is_none() {
+            *producer = Some(global_queue().lock().unwrap().add_producer());
+        }
+    });
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// pushes one formatted message through this thread's producer. Not meant to be
+/// called directly — go through the `log!` macro, which does the formatting.
+pub fn emit(level: LogLevel, text: String) {
+    init_thread_logger();
+    let timestamp_millis = now_millis();
+    PRODUCER.with(|cell| {
+        let producer = cell.borrow();
+        // the global log queue never has a per-consumer capacity configured, so
+        // this can't actually fail; still, a logging call is the last place that
+        // should ever panic, so discard rather than `unwrap`.
+        let _ = producer.as_ref().unwrap().write(vec![LogMsg {
+            level,
+            text,
+            timestamp_millis,
+        }]);
+    });
+}
+
+/// drains every message logged so far (from any thread) into `out`, in the order
+/// each producer's writes were observed. Meant to be called from one background/UI
+/// thread — like `MsgQueue` generally, nothing stops a second caller from draining
+/// the same collector too, but then the two callers split the messages between them
+/// rather than each seeing everything.
+pub fn retrieve_log_messages(out: &mut Vec<LogMsg>) {
+    out.extend(global_collector().lock().unwrap().read_all());
+}
+
+/// formats a message and emits it through the calling thread's producer. The emit
+/// path takes the shared locks described in the module doc on every call, plus the
+/// global queue's mutex once per thread for the lazy producer setup.
+/// `level` is a `LogLevel` variant; the rest is a `format!`-style template.
+///
+/// ```ignore
+/// ringbuf::log!(ringbuf::logging::LogLevel::Warn, "buffer underrun: {} frames", n);
+/// ```
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        $crate::logging::emit($level, format!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    // both cases share the one process-wide global queue/collector, so they're
+    // exercised in a single test rather than two — running them as separate
+    // `#[test]` fns would let cargo's parallel test threads race each other's
+    // `retrieve_log_messages` against each other's assertions.
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_log_macro_reaches_the_collector_from_any_thread() {
+        crate::log!(LogLevel::Info, "hello {}", "world");
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| thread::spawn(move || crate::log!(LogLevel::Debug, "thread {}", i)))
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut out = Vec::new();
+        retrieve_log_messages(&mut out);
+        assert!(out
+            .iter()
+            .any(|m| m.level == LogLevel::Info && m.text == "hello world"));
+        for i in 0..4 {
+            assert!(out.iter().any(|m| m.text == format!("thread {}", i)));
+        }
+    }
+}